@@ -1,9 +1,14 @@
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt, io,
+    sync::Arc,
+};
 
 use crate::{
-    parse::{self, Tokenize},
+    parse::{self, BlockKind, HelperCall, Location, Node, Param, Token, Tokenize},
     value::Value,
-    Object, Variable,
+    Object, Template, Variable,
 };
 
 /// Context for expanding templates
@@ -14,15 +19,118 @@ use crate::{
 /// ctx.define(Variable::single("a"), "b");
 /// assert_eq!(ctx.render("{{ a }}"), Ok("b".to_owned()));
 /// ```
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Context<'a> {
     vars: HashMap<Cow<'a, str>, Value<'a>>,
+    missing: MissingPolicy,
+    options: parse::Options,
+    partials: HashMap<String, Template>,
+    max_partial_depth: usize,
+    indent: bool,
+    helpers: HashMap<String, Helper>,
+}
+impl Default for Context<'_> {
+    fn default() -> Self {
+        Self {
+            vars: HashMap::new(),
+            missing: MissingPolicy::default(),
+            options: parse::Options::default(),
+            partials: HashMap::new(),
+            max_partial_depth: DEFAULT_MAX_PARTIAL_DEPTH,
+            indent: false,
+            helpers: HashMap::new(),
+        }
+    }
+}
+
+/// How deeply partials may be nested unless changed with
+/// [`set_max_partial_depth`](Context::set_max_partial_depth)
+pub const DEFAULT_MAX_PARTIAL_DEPTH: usize = 64;
+
+/// What to do when a template refers to a variable which is not defined
+///
+/// ```
+/// # use handybars::{Context, MissingPolicy};
+/// let ctx = Context::new().with_missing_policy(MissingPolicy::Placeholder("<unset>".to_owned()));
+/// assert_eq!(ctx.render("a: {{ a }}"), Ok("a: <unset>".to_owned()));
+///
+/// let ctx = Context::new().with_missing_policy(MissingPolicy::callback(|var| var.to_string().to_uppercase()));
+/// assert_eq!(ctx.render("{{ x.y }}"), Ok("X.Y".to_owned()));
+/// ```
+///
+/// Note this only applies to variables which do not exist. Expanding an object or indexing past the
+/// end of a list is always an error.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub enum MissingPolicy {
+    /// Fail with [`Error::MissingVariable`]
+    #[default]
+    Strict,
+    /// Leave the block in the output
+    ///
+    /// The block is written back exactly as it appears in the template. [`Context::expand`] has no
+    /// template to copy from and instead gives `{{ var }}`, using the
+    /// [delimiters](parse::Options::delimiters) of the context.
+    Keep,
+    /// Expand to an empty string
+    Empty,
+    /// Expand to a fixed string
+    Placeholder(String),
+    /// Expand to the result of calling a function with the missing variable
+    ///
+    /// See [`callback`](MissingPolicy::callback)
+    Callback(Arc<dyn Fn(&Variable<'_>) -> String + Send + Sync>),
+}
+impl MissingPolicy {
+    /// Construct a [`Callback`](MissingPolicy::Callback) policy
+    pub fn callback(f: impl Fn(&Variable<'_>) -> String + Send + Sync + 'static) -> Self {
+        Self::Callback(Arc::new(f))
+    }
+}
+impl std::fmt::Debug for MissingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strict => f.write_str("Strict"),
+            Self::Keep => f.write_str("Keep"),
+            Self::Empty => f.write_str("Empty"),
+            Self::Placeholder(p) => f.debug_tuple("Placeholder").field(p).finish(),
+            Self::Callback(_) => f.write_str("Callback(..)"),
+        }
+    }
+}
+/// Callbacks are only equal if they are the same function object
+impl PartialEq for MissingPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Placeholder(l), Self::Placeholder(r)) => l == r,
+            (Self::Callback(l), Self::Callback(r)) => Arc::ptr_eq(l, r),
+            (l, r) => std::mem::discriminant(l) == std::mem::discriminant(r),
+        }
+    }
+}
+impl Eq for MissingPolicy {}
+/// Function which templates can call, see [`register_helper`](Context::register_helper)
+#[derive(Clone)]
+struct Helper(Arc<HelperFn>);
+pub(crate) type HelperFn =
+    dyn Fn(&[Value<'_>], &Object<'_>) -> Result<Value<'static>> + Send + Sync;
+impl std::fmt::Debug for Helper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Helper(..)")
+    }
 }
+/// Helpers are only equal if they are the same function object
+impl PartialEq for Helper {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for Helper {}
 type Result<T, E = Error> = std::result::Result<T, E>;
 impl std::error::Error for Error {}
 
 /// Errors that may happen during rendering
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
     /// Forwarded from parsing
@@ -31,6 +139,36 @@ pub enum Error {
     MissingVariable(Variable<'static>),
     /// Tried to expand an object template variable
     TriedToExpandObject(Variable<'static>),
+    /// Tried to expand a list template variable, use `{{#each}}` to expand its elements
+    TriedToExpandList(Variable<'static>),
+    /// A variable indexes past the end of a list, e.g. `{{ servers.2 }}` with only 2 servers
+    IndexOutOfRange(Variable<'static>),
+    /// Tried to include a partial which has not been [registered](Context::register_partial)
+    MissingPartial(String),
+    /// A partial includes itself without anything changing in between, so it would never finish
+    ///
+    /// Contains the names of the partials from the first inclusion of the repeated one to where
+    /// it is included again.
+    RecursivePartial(Vec<String>),
+    /// Partials are nested more deeply than the [limit](Context::set_max_partial_depth)
+    PartialDepthExceeded {
+        /// Partial which would have exceeded the limit
+        name: String,
+        #[allow(missing_docs)]
+        limit: usize,
+    },
+    /// Tried to call a helper which has not been [registered](Context::register_helper)
+    UnknownHelper(String),
+    /// A helper failed, with a description of the problem
+    ///
+    /// This is the error [helpers](Context::register_helper) should return for bad arguments
+    Helper(String),
+    /// Writing to the output failed
+    ///
+    /// Only returned by [`render_to_io`](Context::render_to_io)
+    Io(io::Error),
+    /// Formatting into the output failed
+    Fmt(fmt::Error),
 }
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -42,21 +180,246 @@ impl std::fmt::Display for Error {
             Error::TriedToExpandObject(var) => {
                 f.write_fmt(format_args!("tried to expand object variable: '{var}'"))
             }
+            Error::TriedToExpandList(var) => {
+                f.write_fmt(format_args!("tried to expand list variable: '{var}'"))
+            }
+            Error::IndexOutOfRange(var) => {
+                f.write_fmt(format_args!("list index out of range: '{var}'"))
+            }
+            Error::MissingPartial(name) => write!(f, "partial is not registered: '{name}'"),
+            Error::RecursivePartial(chain) => {
+                write!(f, "partial includes itself: '{}'", chain.join("' > '"))
+            }
+            Error::PartialDepthExceeded { name, limit } => write!(
+                f,
+                "including partial '{name}' exceeds the maximum depth of {limit}"
+            ),
+            Error::UnknownHelper(name) => write!(f, "unknown helper: '{name}'"),
+            Error::Helper(message) => write!(f, "helper: {message}"),
+            Error::Io(e) => f.write_fmt(format_args!("io: {e}")),
+            Error::Fmt(e) => f.write_fmt(format_args!("fmt: {e}")),
+        }
+    }
+}
+/// I/O errors are compared by their [`kind`](io::Error::kind) only
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Parse(l), Self::Parse(r)) => l == r,
+            (Self::MissingVariable(l), Self::MissingVariable(r)) => l == r,
+            (Self::TriedToExpandObject(l), Self::TriedToExpandObject(r)) => l == r,
+            (Self::TriedToExpandList(l), Self::TriedToExpandList(r)) => l == r,
+            (Self::IndexOutOfRange(l), Self::IndexOutOfRange(r)) => l == r,
+            (Self::MissingPartial(l), Self::MissingPartial(r)) => l == r,
+            (Self::RecursivePartial(l), Self::RecursivePartial(r)) => l == r,
+            (
+                Self::PartialDepthExceeded { name, limit },
+                Self::PartialDepthExceeded {
+                    name: r_name,
+                    limit: r_limit,
+                },
+            ) => name == r_name && limit == r_limit,
+            (Self::UnknownHelper(l), Self::UnknownHelper(r)) => l == r,
+            (Self::Helper(l), Self::Helper(r)) => l == r,
+            (Self::Io(l), Self::Io(r)) => l.kind() == r.kind(),
+            (Self::Fmt(l), Self::Fmt(r)) => l == r,
+            _ => false,
         }
     }
 }
+impl Eq for Error {}
 
 impl From<parse::Error> for Error {
     fn from(value: parse::Error) -> Self {
         Self::Parse(value)
     }
 }
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<fmt::Error> for Error {
+    fn from(value: fmt::Error) -> Self {
+        Self::Fmt(value)
+    }
+}
+
+/// Problem found in a template while rendering it
+///
+/// See [`check`](Context::check) and [`render_all_errors`](Context::render_all_errors)
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    location: Location,
+    error: Error,
+}
+impl Diagnostic {
+    /// Location in the template of the problem
+    ///
+    /// For errors caused by a variable this is the location of the `{{` of its block. Problems
+    /// inside of a partial are reported at the `{{>` which included it.
+    pub fn location(&self) -> Location {
+        self.location
+    }
+    /// The error which occurred
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+    /// The variable which caused the error, if any
+    pub fn variable(&self) -> Option<&Variable<'static>> {
+        match &self.error {
+            Error::MissingVariable(v)
+            | Error::TriedToExpandObject(v)
+            | Error::TriedToExpandList(v)
+            | Error::IndexOutOfRange(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {line} column {col}",
+            self.error,
+            line = self.location.line + 1,
+            col = self.location.col + 1
+        )
+    }
+}
+
+/// Output which throws away everything written to it
+struct Discard;
+impl fmt::Write for Discard {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Adapter for writing into an [`io::Write`] through [`fmt::Write`]
+///
+/// [`fmt::Error`] carries no information so the underlying error is stashed here instead
+struct IoWriter<'w, W> {
+    inner: &'w mut W,
+    error: Option<io::Error>,
+}
+impl<W: io::Write> fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+fn render_to_io<W: io::Write>(
+    output: &mut W,
+    render: impl FnOnce(&mut IoWriter<'_, W>) -> Result<()>,
+) -> Result<()> {
+    let mut writer = IoWriter {
+        inner: output,
+        error: None,
+    };
+    match render(&mut writer) {
+        Err(Error::Fmt(e)) => Err(writer.error.take().map_or(Error::Fmt(e), Error::Io)),
+        r => r,
+    }
+}
+/// Value which a block such as `{{#each}}` or `{{#with}}` is rendering its body for
+struct Scope<'s, 'a> {
+    /// `this` in templates
+    this: &'s Value<'a>,
+    /// Set for the elements of an `{{#each}}`
+    each: Option<EachData<'s>>,
+    parent: Option<&'s Scope<'s, 'a>>,
+}
+
+/// Position of an element in an `{{#each}}`, `@index` and friends in templates
+#[derive(Clone, Copy)]
+struct EachData<'s> {
+    /// Name of the property when iterating over an object
+    key: Option<&'s str>,
+    index: usize,
+    len: usize,
+}
+
+/// Helper which ships with handybars, see [`helpers`](crate::helpers)
+#[cfg(feature = "helpers")]
+fn builtin_helper(name: &str) -> Option<&'static HelperFn> {
+    crate::helpers::builtin(name)
+}
+#[cfg(not(feature = "helpers"))]
+fn builtin_helper(_: &str) -> Option<&'static HelperFn> {
+    None
+}
+
+/// Write `s` with every line after the first indented by `indent` spaces
+///
+/// Empty lines are left empty so that no trailing whitespace is added.
+fn write_indented(output: &mut impl fmt::Write, s: &str, indent: usize) -> fmt::Result {
+    let mut lines = s.split('\n');
+    // `split` always returns at least one item
+    output.write_str(lines.next().unwrap_or_default())?;
+    for line in lines {
+        output.write_char('\n')?;
+        if !line.is_empty() && line != "\r" {
+            write!(output, "{:indent$}", "")?;
+        }
+        output.write_str(line)?;
+    }
+    Ok(())
+}
+
+/// Partial which is being rendered, for detecting runaway recursion
+struct PartialFrame<'p, 'a> {
+    name: &'p str,
+    /// `this` when the partial was included
+    this: Option<&'p Value<'a>>,
+    /// Number of partials being rendered, including this one
+    depth: usize,
+    /// Location of the outermost `{{>`, problems inside partials are reported there
+    location: Location,
+    parent: Option<&'p PartialFrame<'p, 'a>>,
+}
+
+/// Why a variable could not be resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Missing {
+    Undefined,
+    /// An index segment was past the end of a list
+    OutOfRange,
+}
+
+/// Index into a list selected by a segment, if it is a number
+fn segment_index(seg: &str) -> Option<usize> {
+    if seg.is_empty() || !seg.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // too many digits to fit is just a very large index
+    Some(seg.parse().unwrap_or(usize::MAX))
+}
+
+/// Follow `path` through nested objects and lists starting at `value`
+fn property_path<'v, 'a>(
+    value: &'v Value<'a>,
+    path: &[Cow<'_, str>],
+) -> Result<&'v Value<'a>, Missing> {
+    path.iter().try_fold(value, |value, seg| match value {
+        Value::Object(o) => o.property(seg).ok_or(Missing::Undefined),
+        Value::List(l) => {
+            let index = segment_index(seg).ok_or(Missing::Undefined)?;
+            l.get(index).ok_or(Missing::OutOfRange)
+        }
+        _ => Err(Missing::Undefined),
+    })
+}
+
 macro_rules! force_object {
     ($entry:expr) => {
         $entry
-            .and_modify(|o| match o {
-                Value::String(_) => *o = Object::new().into(),
-                Value::Object(_) => {}
+            .and_modify(|o| {
+                if !o.is_object() {
+                    *o = Object::new().into()
+                }
             })
             .or_insert(Object::new().into())
             .as_object_mut()
@@ -69,6 +432,13 @@ impl<'a> Context<'a> {
     pub fn new() -> Self {
         Self::default()
     }
+    /// Create a context with the properties of `object` as its variables
+    pub(crate) fn from_object(object: Object<'a>) -> Self {
+        Self {
+            vars: object.values.into_iter().collect(),
+            ..Self::default()
+        }
+    }
     /// Map a variable to a value for template expansion
     ///
     /// Values are merged into existing definitions where possible
@@ -106,12 +476,12 @@ impl<'a> Context<'a> {
                 self.vars
                     .entry(s.clone())
                     .and_modify(|o| match o {
-                        Value::String(_) => {
-                            *o = value.take().unwrap().into();
-                        }
                         Value::Object(o) => {
                             o.add_property(s, value.take().unwrap());
                         }
+                        _ => {
+                            *o = value.take().unwrap().into();
+                        }
                     })
                     .or_insert_with(|| value.take().unwrap().into());
             }
@@ -123,6 +493,148 @@ impl<'a> Context<'a> {
         self.define(var, value);
         self
     }
+    /// Set how variables which are not defined are expanded
+    ///
+    /// Defaults to [`MissingPolicy::Strict`]
+    pub fn set_missing_policy(&mut self, policy: MissingPolicy) -> &mut Self {
+        self.missing = policy;
+        self
+    }
+    /// Set the options used to parse templates passed to [`render`](Context::render) and friends
+    pub fn set_parse_options(&mut self, options: parse::Options) -> &mut Self {
+        self.options = options;
+        self
+    }
+    /// Builder version of [`set_parse_options`](Context::set_parse_options)
+    ///
+    /// ```
+    /// # use handybars::{Context, parse::Options};
+    /// let ctx = Context::new().with_parse_options(Options::new().lenient(true));
+    /// assert_eq!(ctx.render("{{ a"), Ok("{{ a".to_owned()));
+    /// ```
+    pub fn with_parse_options(mut self, options: parse::Options) -> Self {
+        self.set_parse_options(options);
+        self
+    }
+    /// Make a template available for including with `{{> name}}`
+    ///
+    /// Partials are rendered with the variables in scope where they are included. Registering a
+    /// partial with the same name as an existing one replaces it.
+    ///
+    /// ```
+    /// # use handybars::{Context, Template};
+    /// let ctx = Context::new()
+    ///     .with_partial("greeting", Template::compile("hello {{ name }}").unwrap())
+    ///     .with_define("name".parse().unwrap(), "world");
+    /// assert_eq!(ctx.render("{{> greeting}}!"), Ok("hello world!".to_owned()));
+    /// ```
+    pub fn register_partial(&mut self, name: impl Into<String>, template: Template) -> &mut Self {
+        self.partials.insert(name.into(), template);
+        self
+    }
+    /// Builder version of [`register_partial`](Context::register_partial)
+    pub fn with_partial(mut self, name: impl Into<String>, template: Template) -> Self {
+        self.register_partial(name, template);
+        self
+    }
+    /// Set how deeply partials may include other partials
+    ///
+    /// Partials may include themselves as long as something changes in between, such as
+    /// rendering the children of a tree with `{{#each}}`. This guards against data which is too
+    /// deeply nested. Defaults to [`DEFAULT_MAX_PARTIAL_DEPTH`].
+    pub fn set_max_partial_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_partial_depth = depth;
+        self
+    }
+    /// Builder version of [`set_max_partial_depth`](Context::set_max_partial_depth)
+    pub fn with_max_partial_depth(mut self, depth: usize) -> Self {
+        self.set_max_partial_depth(depth);
+        self
+    }
+    /// Indent multi-line values to line up with the `{{` they replace
+    ///
+    /// Every line of an expanded value after the first is indented with spaces up to the column
+    /// of the opening `{{` in the template, so that inserting a certificate or a script into an
+    /// indented YAML block keeps it valid. Defaults to `false`.
+    ///
+    /// ```
+    /// # use handybars::Context;
+    /// let ctx = Context::new()
+    ///     .with_define("script".parse().unwrap(), "set -e\nmake")
+    ///     .with_indent_multiline(true);
+    /// assert_eq!(
+    ///     ctx.render("run: |\n  {{ script }}\n"),
+    ///     Ok("run: |\n  set -e\n  make\n".to_owned())
+    /// );
+    /// ```
+    ///
+    /// The column is the one in the template, values expanded earlier on the same line or the
+    /// indentation of an included partial are not taken into account.
+    pub fn set_indent_multiline(&mut self, indent: bool) -> &mut Self {
+        self.indent = indent;
+        self
+    }
+    /// Builder version of [`set_indent_multiline`](Context::set_indent_multiline)
+    pub fn with_indent_multiline(mut self, indent: bool) -> Self {
+        self.set_indent_multiline(indent);
+        self
+    }
+    /// Make a function available to templates as `{{ name arg1 arg2 key=value }}`
+    ///
+    /// The positional arguments are passed in `args` and the `key=value` arguments as the
+    /// properties of `hash`. Arguments which are variables are looked up where the helper is
+    /// called, string literals (`"text"`) and numbers are passed as strings. The helper must
+    /// return a string value, which is expanded in place of the block. Registering a helper with
+    /// the same name as an existing one replaces it.
+    ///
+    /// ```
+    /// # use handybars::{Context, Error, Value};
+    /// let ctx = Context::new()
+    ///     .with_helper("upper", |args, _| match args {
+    ///         [Value::String(s)] => Ok(s.to_uppercase().into()),
+    ///         _ => Err(Error::Helper("upper takes a single string".to_owned())),
+    ///     })
+    ///     .with_define("name".parse().unwrap(), "world");
+    /// assert_eq!(ctx.render("hello {{ upper name }}"), Ok("hello WORLD".to_owned()));
+    /// assert_eq!(ctx.render(r#"{{ upper "a" "b" }}"#), Err(Error::Helper("upper takes a single string".to_owned())));
+    /// assert_eq!(ctx.render("{{ shout name }}"), Err(Error::UnknownHelper("shout".to_owned())));
+    /// ```
+    ///
+    /// Variables which are not defined are handled according to the [`MissingPolicy`]. With
+    /// [`Strict`](MissingPolicy::Strict) the call is an error and with
+    /// [`Keep`](MissingPolicy::Keep) the helper is not called and the whole block is left in the
+    /// output. Otherwise the helper is passed the string the variable would have expanded to: `""`
+    /// for [`Empty`](MissingPolicy::Empty), the placeholder or the result of the callback.
+    ///
+    /// With the `helpers` feature a set of [built-in helpers](crate::helpers) is also available.
+    pub fn register_helper(
+        &mut self,
+        name: impl Into<String>,
+        helper: impl Fn(&[Value<'_>], &Object<'_>) -> Result<Value<'static>> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.helpers.insert(name.into(), Helper(Arc::new(helper)));
+        self
+    }
+    /// Builder version of [`register_helper`](Context::register_helper)
+    pub fn with_helper(
+        mut self,
+        name: impl Into<String>,
+        helper: impl Fn(&[Value<'_>], &Object<'_>) -> Result<Value<'static>> + Send + Sync + 'static,
+    ) -> Self {
+        self.register_helper(name, helper);
+        self
+    }
+    /// Builder version of [`set_missing_policy`](Context::set_missing_policy)
+    ///
+    /// ```
+    /// # use handybars::{Context, MissingPolicy};
+    /// let ctx = Context::new().with_missing_policy(MissingPolicy::Keep);
+    /// assert_eq!(ctx.render("{{a.b}} {{~ c ~}}"), Ok("{{a.b}}{{~ c ~}}".to_owned()));
+    /// ```
+    pub fn with_missing_policy(mut self, policy: MissingPolicy) -> Self {
+        self.set_missing_policy(policy);
+        self
+    }
     /// Resolve a variable added with [`define`](Context::define)
     ///
     /// This will "drill down" to the lowest point it can get
@@ -151,18 +663,13 @@ impl<'a> Context<'a> {
     ///     Some(&Value::String("y".into()))
     /// );
     /// ```
-    pub fn get_value(&self, var: &Variable<'a>) -> Option<&Value<'a>> {
-        match &var.inner {
-            crate::VariableInner::Segments(segs) => {
-                let mut parent = self.vars.get(&segs[0])?;
-                let last = segs.last().unwrap();
-                for level in segs.iter().skip(1).take(segs.len() - 2) {
-                    parent = parent.as_object()?.property(level)?;
-                }
-                parent.as_object()?.property(last)
-            }
-            crate::VariableInner::Single(s) => self.vars.get(s),
-        }
+    pub fn get_value(&self, var: &Variable<'_>) -> Option<&Value<'a>> {
+        self.lookup(var.segments()).ok()
+    }
+    fn lookup(&self, segs: &[Cow<'_, str>]) -> Result<&Value<'a>, Missing> {
+        let (first, rest) = segs.split_first().ok_or(Missing::Undefined)?;
+        let root = self.vars.get(first.as_ref()).ok_or(Missing::Undefined)?;
+        property_path(root, rest)
     }
     /// Expand a single variable
     ///
@@ -173,14 +680,101 @@ impl<'a> Context<'a> {
     /// assert_eq!(ctx.expand(&var), Ok("b".to_owned()));
     /// ```
     pub fn expand(&self, var: &Variable<'a>) -> Result<String> {
-        let val = self
-            .get_value(var)
-            .ok_or_else(|| Error::MissingVariable(var.clone().into_owned()))?;
-        if val.is_object() {
-            Err(Error::TriedToExpandObject(var.clone().into_owned()))
-        } else {
-            Ok(val.as_string().unwrap().clone().into_owned())
+        self.expand_str(var, self.lookup(var.segments()))
+            .map(Cow::into_owned)
+    }
+    /// Whether a variable resolved to `value` is kept in the output as it is in the template
+    fn keeps<T>(&self, value: &Result<T, Missing>) -> bool {
+        matches!(
+            (value, &self.missing),
+            (Err(Missing::Undefined), MissingPolicy::Keep)
+        )
+    }
+    /// Expand `var` which was resolved to `value`
+    fn expand_str<'v>(
+        &'v self,
+        var: &Variable<'_>,
+        value: Result<&'v Value<'_>, Missing>,
+    ) -> Result<Cow<'v, str>> {
+        let val = match value {
+            Ok(val) => val,
+            Err(Missing::OutOfRange) => {
+                return Err(Error::IndexOutOfRange(var.clone().into_owned()))
+            }
+            Err(Missing::Undefined) => {
+                return match &self.missing {
+                    MissingPolicy::Strict => Err(Error::MissingVariable(var.clone().into_owned())),
+                    MissingPolicy::Keep => Ok(format!(
+                        "{open} {var} {close}",
+                        open = self.options.open(),
+                        close = self.options.close()
+                    )
+                    .into()),
+                    MissingPolicy::Empty => Ok("".into()),
+                    MissingPolicy::Placeholder(p) => Ok(p.as_str().into()),
+                    MissingPolicy::Callback(f) => Ok(f(var).into()),
+                }
+            }
+        };
+        match val {
+            Value::String(s) => Ok(s.as_ref().into()),
+            Value::Object(_) => Err(Error::TriedToExpandObject(var.clone().into_owned())),
+            Value::List(_) => Err(Error::TriedToExpandList(var.clone().into_owned())),
+        }
+    }
+    /// Resolve a variable inside of `scope`
+    ///
+    /// Data variables (`@index` etc) refer to the innermost `{{#each}}` and `this` to the innermost
+    /// scope. `../` moves out one scope and `@root` to the context itself. Other variables are
+    /// looked up in each scope from the innermost outward and finally in the context.
+    fn resolve<'v>(
+        &'v self,
+        var: &Variable<'_>,
+        scope: Option<&Scope<'v, 'a>>,
+    ) -> Result<Cow<'v, Value<'a>>, Missing> {
+        self.resolve_segments(var.segments(), scope)
+    }
+    fn resolve_segments<'v>(
+        &'v self,
+        segs: &[Cow<'_, str>],
+        scope: Option<&Scope<'v, 'a>>,
+    ) -> Result<Cow<'v, Value<'a>>, Missing> {
+        let data = |value: Option<Value<'static>>| match value {
+            Some(value) if segs.len() == 1 => Ok(Cow::Owned(value)),
+            _ => Err(Missing::Undefined),
+        };
+        let each = || std::iter::successors(scope, |s| s.parent).find_map(|s| s.each);
+        match (segs[0].as_ref(), scope) {
+            ("@root", _) => self.lookup(&segs[1..]).map(Cow::Borrowed),
+            ("..", _) => self.resolve_exact(&segs[1..], scope.and_then(|s| s.parent)),
+            ("this", _) => self.resolve_exact(segs, scope),
+            ("@index", _) => data(each().map(|e| e.index.into())),
+            ("@first", _) => data(each().map(|e| (e.index == 0).into())),
+            ("@last", _) => data(each().map(|e| (e.index + 1 == e.len).into())),
+            ("@key", _) => data(each().and_then(|e| e.key).map(|k| k.to_owned().into())),
+            (_, None) => self.lookup(segs).map(Cow::Borrowed),
+            (_, Some(scope)) => match property_path(scope.this, segs) {
+                Err(Missing::Undefined) => self.resolve_segments(segs, scope.parent),
+                value => value.map(Cow::Borrowed),
+            },
+        }
+    }
+    /// Resolve `segs` in exactly `scope`, without falling back to the scopes outside of it
+    fn resolve_exact<'v>(
+        &'v self,
+        segs: &[Cow<'_, str>],
+        scope: Option<&Scope<'v, 'a>>,
+    ) -> Result<Cow<'v, Value<'a>>, Missing> {
+        let path = match segs.first().map(AsRef::as_ref) {
+            Some("..") => return self.resolve_exact(&segs[1..], scope.and_then(|s| s.parent)),
+            Some("this") => &segs[1..],
+            _ => segs,
+        };
+        match scope {
+            Some(scope) => property_path(scope.this, path),
+            None => self.lookup(path),
         }
+        .map(Cow::Borrowed)
     }
 
     /// Render a template
@@ -191,18 +785,404 @@ impl<'a> Context<'a> {
     /// let ctx = Context::new().with_define(var.clone(), "b");
     /// assert_eq!(ctx.render("some text {{ a }}"), Ok("some text b".to_owned()));
     /// ```
-    pub fn render<'b>(&self, input: &'b str) -> Result<String> {
+    pub fn render(&self, input: &str) -> Result<String> {
         let mut output = String::new();
-        for token in Tokenize::<'b>::new(input) {
-            let token = token?;
-            match token {
-                parse::Token::Variable(v) => output.push_str(&self.expand(&v)?),
-                parse::Token::Str(s) => {
-                    output.push_str(s);
+        self.render_to_fmt(input, &mut output)?;
+        Ok(output)
+    }
+    /// Render a template into a [`fmt::Write`]
+    ///
+    /// The whole template is parsed before anything is written. Output is then written as the
+    /// template is expanded so `output` may contain a partially rendered template if an error is
+    /// encountered.
+    ///
+    /// ```
+    /// # use handybars::{Context, Variable};
+    /// let ctx = Context::new().with_define(Variable::single("a"), "b");
+    /// let mut output = String::from("> ");
+    /// ctx.render_to_fmt("{{ a }}", &mut output).unwrap();
+    /// assert_eq!(output, "> b");
+    /// ```
+    pub fn render_to_fmt(&self, input: &str, output: &mut impl fmt::Write) -> Result<()> {
+        let nodes = parse::parse_with_options(input, &self.options)?;
+        self.render_nodes(&nodes, output, None, None, &mut |d| Err(d.error))
+    }
+    /// Render a template into an [`io::Write`]
+    ///
+    /// Each piece of the template is written as soon as it is expanded, so consider wrapping
+    /// unbuffered writers (such as [`File`](std::fs::File)) in a [`BufWriter`](std::io::BufWriter).
+    /// Errors from `output` are reported as [`Error::Io`].
+    ///
+    /// ```
+    /// # use handybars::{Context, Variable};
+    /// let ctx = Context::new().with_define(Variable::single("a"), "b");
+    /// let mut output = Vec::new();
+    /// ctx.render_to_io("some text {{ a }}", &mut output).unwrap();
+    /// assert_eq!(output, b"some text b");
+    /// ```
+    pub fn render_to_io(&self, input: &str, output: &mut impl io::Write) -> Result<()> {
+        render_to_io(output, |w| self.render_to_fmt(input, w))
+    }
+    /// Render a template, continuing past errors
+    ///
+    /// Unlike [`render`](Context::render) this does not stop at the first variable which cannot be
+    /// expanded. Instead every such variable is reported. A parse error still stops rendering, since
+    /// the rest of the template cannot be tokenized, and is reported as the last diagnostic.
+    ///
+    /// ```
+    /// # use handybars::{Context, Error, Variable, parse::Location};
+    /// let ctx = Context::new().with_define(Variable::single("a"), "b");
+    /// let errors = ctx.render_all_errors("{{ a }} {{ x }}\n{{ y }}").unwrap_err();
+    /// assert_eq!(errors.len(), 2);
+    /// assert_eq!(errors[0].variable(), Some(&Variable::single("x")));
+    /// assert_eq!(errors[0].location(), Location::new(8, 0));
+    /// assert_eq!(errors[1].error(), &Error::MissingVariable(Variable::single("y")));
+    /// assert_eq!(errors[1].location(), Location::new(0, 1));
+    /// ```
+    pub fn render_all_errors(&self, input: &str) -> Result<String, Vec<Diagnostic>> {
+        let mut output = String::new();
+        let diagnostics = self.render_collecting_errors(input, &mut output);
+        if diagnostics.is_empty() {
+            Ok(output)
+        } else {
+            Err(diagnostics)
+        }
+    }
+    /// Find every problem which would occur rendering a template
+    ///
+    /// This is [`render_all_errors`](Context::render_all_errors) without the output, an empty list
+    /// means the template would render successfully.
+    ///
+    /// ```
+    /// # use handybars::{Context, Variable};
+    /// let ctx = Context::new().with_define(Variable::single("a"), "b");
+    /// assert!(ctx.check("{{ a }}").is_empty());
+    /// assert_eq!(ctx.check("{{ b }} {{ c }}").len(), 2);
+    /// ```
+    pub fn check(&self, input: &str) -> Vec<Diagnostic> {
+        self.render_collecting_errors(input, &mut Discard)
+    }
+    fn render_collecting_errors(
+        &self,
+        input: &str,
+        output: &mut impl fmt::Write,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let (nodes, parsed) = parse::build_tree(Tokenize::with_options(input, &self.options));
+        // `on_error` never fails so neither does rendering
+        let _ = self.render_nodes(&nodes, output, None, None, &mut |d| {
+            diagnostics.push(d);
+            Ok(())
+        });
+        if let Err(e) = parsed {
+            diagnostics.push(Diagnostic {
+                location: e.location(),
+                error: e.into(),
+            });
+        }
+        diagnostics
+    }
+    /// Render a precompiled template
+    ///
+    /// See [`Template::render`]
+    pub fn render_template(&self, template: &Template) -> Result<String> {
+        let mut output = String::new();
+        self.render_template_to_fmt(template, &mut output)?;
+        Ok(output)
+    }
+    pub(crate) fn render_template_to_fmt(
+        &self,
+        template: &Template,
+        output: &mut impl fmt::Write,
+    ) -> Result<()> {
+        self.render_nodes(template.nodes(), output, None, None, &mut |d| Err(d.error))
+    }
+    pub(crate) fn render_template_to_io(
+        &self,
+        template: &Template,
+        output: &mut impl io::Write,
+    ) -> Result<()> {
+        render_to_io(output, |w| self.render_template_to_fmt(template, w))
+    }
+    /// Render `nodes`, passing the problem to `on_error` whenever a variable cannot be expanded
+    ///
+    /// Rendering stops if `on_error` returns an error
+    fn render_nodes(
+        &self,
+        nodes: &[Node<'_>],
+        output: &mut impl fmt::Write,
+        scope: Option<&Scope<'_, 'a>>,
+        partial: Option<&PartialFrame<'_, 'a>>,
+        on_error: &mut impl FnMut(Diagnostic) -> Result<()>,
+    ) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Str(s) => output.write_str(s)?,
+                Node::Variable(var, location, source) => {
+                    let value = self.resolve(var, scope);
+                    if self.keeps(&value) {
+                        output.write_str(source)?;
+                        continue;
+                    }
+                    let expanded = self
+                        .expand_str(var, value.as_deref().map_err(|m| *m))
+                        .and_then(|v| self.write_expanded(output, &v, *location));
+                    if let Err(error) = expanded {
+                        on_error(Diagnostic {
+                            location: partial.map_or(*location, |p| p.location),
+                            error,
+                        })?;
+                    }
+                }
+                Node::Helper(call, location, source) => {
+                    let expanded = self.call_helper(call, scope).and_then(|v| match v {
+                        Some(v) => self.write_expanded(output, &v, *location),
+                        None => Ok(output.write_str(source)?),
+                    });
+                    if let Err(error) = expanded {
+                        on_error(Diagnostic {
+                            location: partial.map_or(*location, |p| p.location),
+                            error,
+                        })?;
+                    }
+                }
+                Node::Block(block) => {
+                    let value = self.resolve(block.variable(), scope).ok();
+                    let value = value.as_deref().filter(|v| v.is_truthy());
+                    match (block.kind(), value) {
+                        (BlockKind::If, Some(_)) | (BlockKind::Unless, None) => {
+                            self.render_nodes(block.body(), output, scope, partial, on_error)?;
+                        }
+                        (BlockKind::Each, Some(value)) => {
+                            self.render_each(block, value, scope, partial, output, on_error)?;
+                        }
+                        (BlockKind::With, Some(this)) => {
+                            let scope = Scope {
+                                this,
+                                each: None,
+                                parent: scope,
+                            };
+                            self.render_nodes(
+                                block.body(),
+                                output,
+                                Some(&scope),
+                                partial,
+                                on_error,
+                            )?;
+                        }
+                        _ => {
+                            self.render_nodes(block.else_body(), output, scope, partial, on_error)?
+                        }
+                    }
+                }
+                Node::Partial(name, location) => {
+                    let this = scope.map(|s| s.this);
+                    let frame = self.enter_partial(name, this, partial).map(|template| {
+                        let frame = PartialFrame {
+                            name,
+                            this,
+                            depth: partial.map_or(1, |p| p.depth + 1),
+                            location: partial.map_or(*location, |p| p.location),
+                            parent: partial,
+                        };
+                        (template, frame)
+                    });
+                    match frame {
+                        Ok((template, frame)) => {
+                            let nodes = template.nodes();
+                            self.render_nodes(nodes, output, scope, Some(&frame), on_error)?;
+                        }
+                        Err(error) => on_error(Diagnostic {
+                            location: partial.map_or(*location, |p| p.location),
+                            error,
+                        })?,
+                    }
                 }
             }
         }
-        Ok(output)
+        Ok(())
+    }
+    /// Write the expansion of the block at `location`
+    fn write_expanded(
+        &self,
+        output: &mut impl fmt::Write,
+        expanded: &str,
+        location: Location,
+    ) -> Result<()> {
+        match self.indent {
+            true => Ok(write_indented(output, expanded, location.col)?),
+            false => Ok(output.write_str(expanded)?),
+        }
+    }
+    /// Call a helper with its arguments looked up in `scope`
+    ///
+    /// `None` if an argument is missing and the tag should be kept as it is
+    fn call_helper(
+        &self,
+        call: &HelperCall<'_>,
+        scope: Option<&Scope<'_, 'a>>,
+    ) -> Result<Option<Cow<'static, str>>> {
+        let helper = match self.helpers.get(call.name()) {
+            Some(helper) => &*helper.0,
+            None => builtin_helper(call.name())
+                .ok_or_else(|| Error::UnknownHelper(call.name().to_owned()))?,
+        };
+        let param = |param: &Param<'_>| match param {
+            Param::Literal(s) => Ok(Some(Value::String(s.to_string().into()))),
+            Param::Variable(var) => match self.resolve(var, scope) {
+                value if self.keeps(&value) => Ok(None),
+                Ok(value) => Ok(Some(value.into_owned())),
+                Err(missing) => self
+                    .expand_str(var, Err(missing))
+                    .map(|s| Some(Value::String(s.into_owned().into()))),
+            },
+        };
+        let mut args = Vec::new();
+        for arg in call.args() {
+            match param(arg)? {
+                Some(arg) => args.push(arg),
+                None => return Ok(None),
+            }
+        }
+        let mut hash = Object::new();
+        for (key, value) in call.hash() {
+            match param(value)? {
+                Some(value) => hash.add_property(key.as_ref(), value),
+                None => return Ok(None),
+            };
+        }
+        match helper(&args, &hash)? {
+            Value::String(s) => Ok(Some(s)),
+            _ => Err(Error::Helper(format!(
+                "'{}' did not return a string",
+                call.name()
+            ))),
+        }
+    }
+    /// Find the partial `name` to include it inside of `partial`
+    ///
+    /// Including a partial which is already being rendered with the same `this` would repeat
+    /// forever, as would going past the depth limit.
+    fn enter_partial(
+        &self,
+        name: &str,
+        this: Option<&Value<'a>>,
+        partial: Option<&PartialFrame<'_, 'a>>,
+    ) -> Result<&Template> {
+        let template = self
+            .partials
+            .get(name)
+            .ok_or_else(|| Error::MissingPartial(name.to_owned()))?;
+        let frames = std::iter::successors(partial, |p| p.parent);
+        let same_this = |p: &PartialFrame<'_, 'a>| match (p.this, this) {
+            (Some(l), Some(r)) => std::ptr::eq(l, r),
+            (l, r) => l.is_none() && r.is_none(),
+        };
+        if let Some(repeat) = frames.clone().position(|p| p.name == name && same_this(p)) {
+            let mut chain = frames
+                .take(repeat + 1)
+                .map(|p| p.name.to_owned())
+                .collect::<Vec<_>>();
+            chain.reverse();
+            chain.push(name.to_owned());
+            return Err(Error::RecursivePartial(chain));
+        }
+        if partial.map_or(0, |p| p.depth) >= self.max_partial_depth {
+            return Err(Error::PartialDepthExceeded {
+                name: name.to_owned(),
+                limit: self.max_partial_depth,
+            });
+        }
+        Ok(template)
+    }
+    /// Render the body of an `{{#each}}` once for every element of `value`
+    ///
+    /// Values which are neither lists nor objects are treated as a list of one
+    fn render_each(
+        &self,
+        block: &parse::Block<'_>,
+        value: &Value<'a>,
+        scope: Option<&Scope<'_, 'a>>,
+        partial: Option<&PartialFrame<'_, 'a>>,
+        output: &mut impl fmt::Write,
+        on_error: &mut impl FnMut(Diagnostic) -> Result<()>,
+    ) -> Result<()> {
+        let mut render = |index, len, key, this| {
+            let scope = Scope {
+                this,
+                each: Some(EachData { key, index, len }),
+                parent: scope,
+            };
+            self.render_nodes(block.body(), output, Some(&scope), partial, on_error)
+        };
+        match value {
+            Value::List(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    render(index, items.len(), None, item)?;
+                }
+            }
+            Value::Object(o) => {
+                for (index, (key, item)) in o.values.iter().enumerate() {
+                    render(index, o.values.len(), Some(key.as_ref()), item)?;
+                }
+            }
+            _ => render(0, 1, None, value)?,
+        }
+        Ok(())
+    }
+    /// Find defined values which a template never uses
+    ///
+    /// Only the leaves of the definitions (i.e. non-object values) are reported. A leaf counts as
+    /// used if the template, or any partial it includes, references it or any object containing
+    /// it. The result is sorted.
+    ///
+    /// ```
+    /// # use handybars::{Context, Object, Variable};
+    /// let ctx = Context::new()
+    ///     .with_define("db".parse().unwrap(), Object::new().with_property("host", "h").with_property("port", "1"))
+    ///     .with_define("user".parse().unwrap(), "u");
+    /// assert_eq!(
+    ///     ctx.unused_defines("{{ db.host }}"),
+    ///     Ok(vec!["db.port".parse().unwrap(), Variable::single("user")])
+    /// );
+    /// ```
+    pub fn unused_defines(&self, input: &str) -> Result<Vec<Variable<'static>>, parse::Error> {
+        let mut used = Vec::new();
+        let mut partials = Vec::new();
+        for token in Tokenize::with_options(input, &self.options) {
+            match token? {
+                Token::Variable(var) | Token::BlockOpen { variable: var, .. } => used.push(var),
+                Token::Helper(call) => used.extend(call.into_variables()),
+                Token::Partial(name) => partials.push(name),
+                _ => {}
+            }
+        }
+        let mut seen = HashSet::new();
+        let mut partial_vars = Vec::new();
+        while let Some(name) = partials.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if let Some(template) = self.partials.get(name.as_ref()) {
+                partial_vars.extend(template.variables());
+                partials.extend(template.partials().map(Cow::Borrowed));
+            }
+        }
+        // paths relative to the context, anything else is covered by the block it is in
+        let used = used
+            .iter()
+            .chain(partial_vars)
+            .map(|v| match v.segments() {
+                [root, rest @ ..] if root == "@root" => rest,
+                segs => &segs[segs.iter().take_while(|s| *s == "..").count()..],
+            })
+            .collect::<Vec<_>>();
+        let mut unused = Vec::new();
+        for (name, value) in &self.vars {
+            collect_unused_leaves(&mut vec![Cow::Borrowed(name)], value, &used, &mut unused);
+        }
+        unused.sort_by_cached_key(ToString::to_string);
+        Ok(unused)
     }
     /// Append another `Context`'s cloned variables.
     ///
@@ -228,6 +1208,83 @@ impl<'a> Context<'a> {
         self.vars.extend(other.vars);
         self
     }
+    /// Merge another `Context`'s variables into this one, property by property
+    ///
+    /// Where both define an object the objects are merged, otherwise the value from `other` wins.
+    ///
+    /// ```
+    /// # use handybars::Context;
+    /// let mut ctx = Context::new()
+    ///     .with_define("db.host".parse().unwrap(), "localhost")
+    ///     .with_define("db.port".parse().unwrap(), "5432");
+    /// ctx.deep_append(Context::new().with_define("db.host".parse().unwrap(), "db.local"));
+    /// assert_eq!(ctx.render("{{ db.host }}:{{ db.port }}"), Ok("db.local:5432".to_owned()));
+    /// ```
+    pub fn deep_append(&mut self, other: Self) {
+        for (name, value) in other.vars {
+            match self.vars.get_mut(&name) {
+                Some(existing) => deep_merge_value(existing, value),
+                None => {
+                    self.vars.insert(name, value);
+                }
+            }
+        }
+    }
+    /// Chained version of [`deep_append`](Context::deep_append)
+    pub fn deep_merge(mut self, other: Self) -> Self {
+        self.deep_append(other);
+        self
+    }
+}
+
+/// Merge `value` into `into`, recursing where both are objects
+fn deep_merge_value<'a>(into: &mut Value<'a>, value: Value<'a>) {
+    match (into, value) {
+        (Value::Object(into), Value::Object(value)) => {
+            for (name, value) in value.values {
+                match into.values.get_mut(&name) {
+                    Some(existing) => deep_merge_value(existing, value),
+                    None => {
+                        into.values.insert(name, value);
+                    }
+                }
+            }
+        }
+        (into, value) => *into = value,
+    }
+}
+
+fn collect_unused_leaves<'v>(
+    path: &mut Vec<Cow<'v, str>>,
+    value: &'v Value<'_>,
+    used: &[&[Cow<'_, str>]],
+    unused: &mut Vec<Variable<'static>>,
+) {
+    let is_used = |u: &&[Cow<'_, str>]| {
+        u.len() <= path.len() && u.iter().zip(path.iter()).all(|(l, r)| l == r)
+    };
+    if used.iter().any(is_used) {
+        return;
+    }
+    match value {
+        Value::Object(o) => {
+            for (name, value) in &o.values {
+                path.push(Cow::Borrowed(name));
+                collect_unused_leaves(path, value, used, unused);
+                path.pop();
+            }
+        }
+        Value::List(l) => {
+            for (index, value) in l.iter().enumerate() {
+                path.push(Cow::Owned(index.to_string()));
+                collect_unused_leaves(path, value, used, unused);
+                path.pop();
+            }
+        }
+        _ => unused.push(Variable::from_parts(
+            path.iter().map(|s| Cow::Owned(s.to_string())),
+        )),
+    }
 }
 
 impl<'a> Extend<(Variable<'a>, Value<'a>)> for Context<'a> {
@@ -289,6 +1346,673 @@ mod tests {
         );
     }
     #[test]
+    fn missing_policy_defaults_to_strict() {
+        assert_eq!(Context::new().missing, MissingPolicy::Strict);
+    }
+    #[test]
+    fn missing_policy_replaces_only_missing_variables() {
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        let render = |policy| {
+            ctx.clone()
+                .with_missing_policy(policy)
+                .render("{{a}} {{ c }}")
+        };
+        assert_eq!(render(MissingPolicy::Keep), Ok("b {{ c }}".to_owned()));
+        assert_eq!(render(MissingPolicy::Empty), Ok("b ".to_owned()));
+        assert_eq!(
+            render(MissingPolicy::Placeholder("?".to_owned())),
+            Ok("b ?".to_owned())
+        );
+        assert_eq!(
+            render(MissingPolicy::callback(|v| format!("<{v}>"))),
+            Ok("b <c>".to_owned())
+        );
+    }
+    #[test]
+    fn missing_policy_keep_uses_custom_delimiters() {
+        let ctx = Context::new()
+            .with_parse_options(parse::Options::new().delimiters("[[", "]]"))
+            .with_missing_policy(MissingPolicy::Keep);
+        assert_eq!(ctx.render("[[a]] {{ b }}"), Ok("[[a]] {{ b }}".to_owned()));
+        assert_eq!(ctx.expand(&Variable::single("a")), Ok("[[ a ]]".to_owned()));
+    }
+    #[test]
+    fn missing_policy_keep_copies_the_tag_unchanged() {
+        let ctx = Context::new()
+            .with_define(Variable::single("list"), vec!["x"])
+            .with_missing_policy(MissingPolicy::Keep)
+            .with_indent_multiline(true);
+        assert_eq!(
+            ctx.render(
+                "{{a.b}}|{{  a.[c d]  }}|{{~ a ~}} |\\\\{{ a }}|{{#each list}}{{../a}}{{/each}}"
+            ),
+            Ok("{{a.b}}|{{  a.[c d]  }}|{{~ a ~}}|\\{{ a }}|{{../a}}".to_owned())
+        );
+    }
+    #[test]
+    fn missing_helper_arguments_follow_the_policy() {
+        let ctx = Context::new()
+            .with_helper("shout", |args, hash| {
+                let args = args.iter().chain(hash.property("c"));
+                Ok(args
+                    .filter_map(Value::as_string)
+                    .map(|s| s.to_uppercase())
+                    .collect::<Vec<_>>()
+                    .join(",")
+                    .into())
+            })
+            .with_define(Variable::single("a"), "x");
+        let render = |policy| {
+            ctx.clone()
+                .with_missing_policy(policy)
+                .render("{{ shout a b }}|{{shout a c=b~}} |")
+        };
+        assert_eq!(
+            render(MissingPolicy::Keep),
+            Ok("{{ shout a b }}|{{shout a c=b~}}|".to_owned())
+        );
+        assert_eq!(render(MissingPolicy::Empty), Ok("X,|X,|".to_owned()));
+        assert_eq!(
+            render(MissingPolicy::Placeholder("?".to_owned())),
+            Ok("X,?|X,?|".to_owned())
+        );
+        assert_eq!(
+            render(MissingPolicy::callback(|var| format!("<{var}>"))),
+            Ok("X,<B>|X,<B>|".to_owned())
+        );
+        assert_eq!(
+            render(MissingPolicy::Strict),
+            Err(Error::MissingVariable(Variable::single("b")))
+        );
+    }
+    #[test]
+    fn missing_policy_does_not_allow_expanding_objects() {
+        let ctx = Context::new()
+            .with_define("a".parse().unwrap(), Object::new())
+            .with_missing_policy(MissingPolicy::Empty);
+        assert_eq!(
+            ctx.render("{{ a }}"),
+            Err(Error::TriedToExpandObject("a".parse().unwrap()))
+        );
+    }
+    #[test]
+    fn check_reports_every_bad_variable_with_location() {
+        let ctx = Context::new()
+            .with_define("a".parse().unwrap(), Object::new().with_property("b", "c"))
+            .with_define("d".parse().unwrap(), "e");
+        let diagnostics = ctx.check("{{ a }} {{ a.b }}\n {{ d }} {{ f.g }} {{ a.b.c }}");
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    location: Location::new(0, 0),
+                    error: Error::TriedToExpandObject("a".parse().unwrap())
+                },
+                Diagnostic {
+                    location: Location::new(9, 1),
+                    error: Error::MissingVariable("f.g".parse().unwrap())
+                },
+                Diagnostic {
+                    location: Location::new(19, 1),
+                    error: Error::MissingVariable("a.b.c".parse().unwrap())
+                },
+            ]
+        );
+    }
+    #[test]
+    fn check_stops_at_parse_errors() {
+        let diagnostics = Context::new().check("{{ a }} {{ b. }} {{ c }}");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].variable(), Some(&Variable::single("a")));
+        assert!(matches!(diagnostics[1].error(), Error::Parse(_)));
+        assert_eq!(diagnostics[1].variable(), None);
+    }
+    #[test]
+    fn render_all_errors_renders_valid_templates() {
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        assert_eq!(ctx.render_all_errors("x {{ a }} y"), Ok("x b y".to_owned()));
+    }
+    #[test]
+    fn unused_defines_skips_leaves_under_used_objects() {
+        let ctx = Context::new()
+            .with_define("a.b.c".parse().unwrap(), "1")
+            .with_define("a.b.d".parse().unwrap(), "2")
+            .with_define("a.e".parse().unwrap(), "3");
+        assert_eq!(
+            ctx.unused_defines("{{ a.b }}"),
+            Ok(vec!["a.e".parse().unwrap()])
+        );
+        assert_eq!(ctx.unused_defines("{{ a }}"), Ok(vec![]));
+        assert_eq!(
+            ctx.unused_defines("{{ a.b.c.x }}").unwrap().len(),
+            3,
+            "referencing below a leaf does not use it"
+        );
+    }
+    #[test]
+    fn comments_are_not_rendered() {
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        assert_eq!(
+            ctx.render("{{! the a variable }}{{ a }}{{!-- {{ missing }} --}}!"),
+            Ok("b!".to_owned())
+        );
+    }
+    #[test]
+    fn whitespace_control_is_applied_when_rendering() {
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        assert_eq!(
+            ctx.render("list:\n  {{~! a comment ~}}\n  - {{ a }}\n"),
+            Ok("list:- b\n".to_owned())
+        );
+    }
+    #[test]
+    fn if_and_unless_blocks_choose_a_branch() {
+        let ctx = Context::new()
+            .with_define("yes".parse().unwrap(), true)
+            .with_define("no".parse().unwrap(), false)
+            .with_define("empty".parse().unwrap(), Object::new())
+            .with_define("obj".parse().unwrap(), Object::new().with_property("a", ""));
+        let render = |input| ctx.render(input).unwrap();
+        assert_eq!(render("{{#if yes}}a{{else}}b{{/if}}"), "a");
+        assert_eq!(render("{{#if no}}a{{else}}b{{/if}}"), "b");
+        assert_eq!(render("{{#if missing}}a{{else}}b{{/if}}"), "b");
+        assert_eq!(render("{{#if empty}}a{{/if}}"), "");
+        assert_eq!(render("{{#if obj}}a{{/if}}"), "a");
+        assert_eq!(render("{{#if obj.a}}a{{/if}}"), "");
+        assert_eq!(render("{{#unless no}}a{{else}}b{{/unless}}"), "a");
+        assert_eq!(render("{{#unless yes}}a{{/unless}}"), "");
+        assert_eq!(
+            render("{{#if yes}}1{{#unless yes}}2{{else}}3{{/unless}}{{/if}}"),
+            "13"
+        );
+    }
+    #[test]
+    fn only_the_rendered_branch_needs_its_variables() {
+        let ctx = Context::new().with_define("a".parse().unwrap(), "x");
+        assert_eq!(
+            ctx.render("{{#if a}}{{ a }}{{else}}{{ b }}{{/if}}"),
+            Ok("x".to_owned())
+        );
+        assert_eq!(
+            ctx.check("{{#unless a}}{{ b }}{{else}}{{ c }}{{/unless}}"),
+            vec![Diagnostic {
+                location: Location::new(28, 0),
+                error: Error::MissingVariable(Variable::single("c"))
+            }]
+        );
+    }
+    #[test]
+    fn block_whitespace_control_removes_blank_lines() {
+        let ctx = Context::new().with_define("debug".parse().unwrap(), true);
+        assert_eq!(
+            ctx.render("a:\n  {{~#if debug}}\n  log: debug\n  {{~/if}}\nb:"),
+            Ok("a:\n  log: debug\nb:".to_owned())
+        );
+    }
+    #[test]
+    fn check_reports_errors_before_an_unclosed_block() {
+        let diagnostics = Context::new().check("{{#if a}}{{ b }}");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].error(),
+            &Error::Parse(parse::Error::new(
+                Location::zero(),
+                parse::ErrorKind::UnclosedBlock(BlockKind::If)
+            ))
+        );
+        let diagnostics = Context::new()
+            .with_define("a".parse().unwrap(), "x")
+            .check("{{#if a}}{{ b }}");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].variable(), Some(&Variable::single("b")));
+    }
+    #[test]
+    fn each_block_iterates_over_lists() {
+        let ctx = Context::new()
+            .with_define("hosts".parse().unwrap(), vec!["a", "b", "c"])
+            .with_define("none".parse().unwrap(), Vec::<&str>::new())
+            .with_define("sep".parse().unwrap(), ", ");
+        assert_eq!(
+            ctx.render(
+                "{{#each hosts}}{{@index}}={{this}}{{#unless @last}}{{sep}}{{/unless}}{{/each}}"
+            ),
+            Ok("0=a, 1=b, 2=c".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#each hosts}}{{#if @first}}{{this}}{{/if}}{{/each}}"),
+            Ok("a".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#each none}}x{{else}}empty{{/each}}"),
+            Ok("empty".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#each missing}}x{{else}}empty{{/each}}"),
+            Ok("empty".to_owned())
+        );
+    }
+    #[test]
+    fn each_block_iterates_over_objects_with_keys() {
+        let ctx = Context::new().with_define(
+            "ports".parse().unwrap(),
+            Object::new()
+                .with_property("http", Object::new().with_property("port", 80))
+                .with_property("https", Object::new().with_property("port", 443)),
+        );
+        assert_eq!(
+            ctx.render("{{#each ports}}{{@key}}:{{port}}/{{this.port}} {{/each}}"),
+            Ok("http:80/80 https:443/443 ".to_owned())
+        );
+    }
+    #[test]
+    fn nested_each_blocks_see_outer_scopes() {
+        let ctx = Context::new()
+            .with_define(
+                "groups".parse().unwrap(),
+                vec![
+                    Object::new()
+                        .with_property("name", "g1")
+                        .with_property("members", vec!["x", "y"]),
+                    Object::new()
+                        .with_property("name", "g2")
+                        .with_property("members", vec!["z"]),
+                ],
+            )
+            .with_define("domain".parse().unwrap(), "example.com");
+        assert_eq!(
+            ctx.render("{{#each groups}}{{#each members}}{{this}}.{{name}}.{{domain}}:{{@index}} {{/each}}{{/each}}"),
+            Ok("x.g1.example.com:0 y.g1.example.com:1 z.g2.example.com:0 ".to_owned())
+        );
+    }
+    #[test]
+    fn data_variables_outside_each_are_missing() {
+        assert_eq!(
+            Context::new().render("{{ @index }}"),
+            Err(Error::MissingVariable(Variable::single("@index")))
+        );
+    }
+    #[test]
+    fn trying_to_expand_a_list_variable_is_an_error() {
+        let ctx = Context::new().with_define("a".parse().unwrap(), vec![1, 2]);
+        assert_eq!(
+            ctx.render("{{ a }}"),
+            Err(Error::TriedToExpandList(Variable::single("a")))
+        );
+    }
+    #[test]
+    fn index_segments_select_list_elements() {
+        let ctx = Context::new().with_define(
+            "servers".parse().unwrap(),
+            (0..11)
+                .map(|i| Object::new().with_property("host", format!("h{i}")))
+                .collect::<Value>(),
+        );
+        assert_eq!(
+            ctx.render("{{ servers.0.host }} {{ servers.[2].host }} {{ servers.10.host }}"),
+            Ok("h0 h2 h10".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#if servers.11}}yes{{else}}no{{/if}}"),
+            Ok("no".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{ servers.x }}"),
+            Err(Error::MissingVariable("servers.x".parse().unwrap()))
+        );
+    }
+    #[test]
+    fn indexing_past_the_end_of_a_list_is_an_error() {
+        let ctx = Context::new()
+            .with_define("a".parse().unwrap(), vec!["x", "y"])
+            .with_missing_policy(MissingPolicy::Empty);
+        for input in ["{{ a.2 }}", "{{ a.[99999999999999999999999] }}"] {
+            let var = input.trim_matches(|c| "{} ".contains(c)).parse().unwrap();
+            assert_eq!(ctx.render(input), Err(Error::IndexOutOfRange(var)));
+        }
+        assert_eq!(
+            ctx.render("{{#each a}}{{ this.0 }}{{/each}}"),
+            Ok("".to_owned())
+        );
+    }
+    #[test]
+    fn unused_defines_reports_list_elements() {
+        let ctx = Context::new().with_define("a".parse().unwrap(), vec!["x", "y"]);
+        assert_eq!(
+            ctx.unused_defines("{{ a.[1] }}"),
+            Ok(vec!["a.0".parse().unwrap()])
+        );
+        assert_eq!(ctx.unused_defines("{{#each a}}{{/each}}"), Ok(vec![]));
+    }
+    #[test]
+    fn quoted_segments_look_up_keys_with_symbols() {
+        let ctx = Context::new()
+            .with_define("labels.[app.kubernetes.io/name]".parse().unwrap(), "web")
+            .with_define(Variable::from_parts(["labels", "team owner"]), "ops")
+            .with_define(Variable::from_parts(["a.b"]), "c");
+        assert_eq!(
+            ctx.render(
+                "{{ labels.[app.kubernetes.io/name] }} {{ labels.[team owner] }} {{ [a.b] }}"
+            ),
+            Ok("web ops c".to_owned())
+        );
+        assert_eq!(
+            ctx.clone()
+                .with_missing_policy(MissingPolicy::Keep)
+                .render("{{ labels.[x/y] }}"),
+            Ok("{{ labels.[x/y] }}".to_owned())
+        );
+        assert_eq!(
+            ctx.unused_defines("{{ labels.[team owner] }}"),
+            Ok(vec![
+                Variable::from_parts(["a.b"]),
+                "labels.[app.kubernetes.io/name]".parse().unwrap()
+            ])
+        );
+    }
+    #[test]
+    fn with_block_pushes_a_scope() {
+        let ctx = Context::new()
+            .with_define(
+                "a.b.c".parse().unwrap(),
+                Object::new().with_property("x", "1"),
+            )
+            .with_define("a.b.y".parse().unwrap(), "2")
+            .with_define("x".parse().unwrap(), "top");
+        assert_eq!(
+            ctx.render("{{#with a.b}}{{#with c}}{{ x }} {{ this.x }} {{ y }} {{ ../y }} {{ @root.x }}{{/with}}{{/with}}"),
+            Ok("1 1 2 2 top".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#with a.b.c}}{{ ../y }}{{/with}}"),
+            Err(Error::MissingVariable("../y".parse().unwrap())),
+            "../ moves out a scope, not up a level of the path"
+        );
+        assert_eq!(
+            ctx.render("{{#with a}}{{#with b}}{{ y }} {{ ../../x }}{{/with}}{{/with}}"),
+            Ok("2 top".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#with nope}}a{{else}}b{{/with}}"),
+            Ok("b".to_owned())
+        );
+    }
+    #[test]
+    fn parent_lookups_skip_inner_scopes() {
+        let ctx = Context::new()
+            .with_define("name".parse().unwrap(), "outer")
+            .with_define(
+                "items".parse().unwrap(),
+                vec![Object::new().with_property("name", "inner")],
+            );
+        assert_eq!(
+            ctx.render("{{#each items}}{{ name }} {{ ../name }} {{ @root.name }}{{/each}}"),
+            Ok("inner outer outer".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{#each items}}{{#with this}}{{ @index }}{{ ../name }}{{/with}}{{/each}}"),
+            Ok("0inner".to_owned())
+        );
+        assert_eq!(ctx.render("{{ @root.name }}"), Ok("outer".to_owned()));
+        assert_eq!(
+            ctx.render("{{#each items}}{{ ../missing }}{{/each}}"),
+            Err(Error::MissingVariable("../missing".parse().unwrap()))
+        );
+    }
+    #[test]
+    fn unused_defines_understands_parent_lookups() {
+        let ctx = Context::new()
+            .with_define("a".parse().unwrap(), "1")
+            .with_define("b".parse().unwrap(), "2")
+            .with_define("c".parse().unwrap(), vec!["3"]);
+        assert_eq!(
+            ctx.unused_defines("{{#each c}}{{ ../a }}{{ @root.b }}{{/each}}"),
+            Ok(vec![])
+        );
+    }
+    fn partial(input: &str) -> Template {
+        Template::compile(input).unwrap()
+    }
+    #[test]
+    fn partials_render_with_the_current_scope() {
+        let ctx = Context::new()
+            .with_partial("item", partial("<{{ name }}>"))
+            .with_partial("list", partial("{{#each items}}{{> item}}{{/each}}"))
+            .with_define("name".parse().unwrap(), "top")
+            .with_define(
+                "items".parse().unwrap(),
+                vec![Object::new().with_property("name", "a"), Object::new()],
+            );
+        assert_eq!(
+            ctx.render("{{> item}} {{> list }}"),
+            Ok("<top> <a><top>".to_owned())
+        );
+    }
+    #[test]
+    fn missing_partials_are_an_error() {
+        let ctx = Context::new().with_define("a".parse().unwrap(), "b");
+        assert_eq!(
+            ctx.render("{{ a }}{{> nope}}"),
+            Err(Error::MissingPartial("nope".to_owned()))
+        );
+    }
+    #[test]
+    fn partials_may_recurse_into_nested_data() {
+        let leaf = Object::new().with_property("name", "c");
+        let tree = Object::new().with_property("name", "a").with_property(
+            "children",
+            vec![
+                Object::new()
+                    .with_property("name", "b")
+                    .with_property("children", vec![leaf]),
+                Object::new().with_property("name", "d"),
+            ],
+        );
+        let ctx = Context::new()
+            .with_partial(
+                "node",
+                // `this.` so that leaves do not pick up their parent's children
+                partial(
+                    "{{ name }}{{#if this.children}}({{#each children}}{{> node}}{{/each}}){{/if}}",
+                ),
+            )
+            .with_define("tree".parse().unwrap(), tree);
+        assert_eq!(
+            ctx.render("{{#with tree}}{{> node}}{{/with}}"),
+            Ok("a(b(c)d)".to_owned())
+        );
+        assert_eq!(
+            ctx.clone()
+                .with_max_partial_depth(2)
+                .render("{{#with tree}}{{> node}}{{/with}}"),
+            Err(Error::PartialDepthExceeded {
+                name: "node".to_owned(),
+                limit: 2
+            })
+        );
+    }
+    #[test]
+    fn partials_which_include_themselves_unchanged_are_an_error() {
+        let ctx = Context::new()
+            .with_partial("a", partial("{{> b}}"))
+            .with_partial("b", partial("{{#each x}}{{> a}}{{/each}}"))
+            .with_partial("c", partial("{{> c}}"))
+            .with_define("x".parse().unwrap(), vec!["1"]);
+        assert_eq!(
+            ctx.render("{{> c}}"),
+            Err(Error::RecursivePartial(vec![
+                "c".to_owned(),
+                "c".to_owned()
+            ]))
+        );
+        // the element of `x` is the same each time around
+        assert_eq!(
+            ctx.render("{{> a}}"),
+            Err(Error::RecursivePartial(vec![
+                "a".to_owned(),
+                "b".to_owned(),
+                "a".to_owned()
+            ]))
+        );
+    }
+    #[test]
+    fn problems_inside_partials_are_reported_where_they_are_included() {
+        let ctx = Context::new()
+            .with_partial("outer", partial("{{ a }}{{> inner}}"))
+            .with_partial("inner", partial("{{ b }}{{> nope}}"));
+        let diagnostics = ctx.check("x\n {{> outer}}");
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.location(), d.error()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    Location::new(1, 1),
+                    &Error::MissingVariable(Variable::single("a"))
+                ),
+                (
+                    Location::new(1, 1),
+                    &Error::MissingVariable(Variable::single("b"))
+                ),
+                (
+                    Location::new(1, 1),
+                    &Error::MissingPartial("nope".to_owned())
+                ),
+            ]
+        );
+    }
+    #[test]
+    fn unused_defines_follows_partials() {
+        let ctx = Context::new()
+            .with_partial("a", partial("{{ x }}{{> b}}{{> a}}"))
+            .with_partial("b", partial("{{ y }}"))
+            .with_define("x".parse().unwrap(), "1")
+            .with_define("y".parse().unwrap(), "2")
+            .with_define("z".parse().unwrap(), "3");
+        assert_eq!(
+            ctx.unused_defines("{{> a}}"),
+            Ok(vec![Variable::single("z")])
+        );
+    }
+    #[test]
+    fn multiline_values_are_only_indented_when_enabled() {
+        let ctx =
+            Context::new().with_define("cert".parse().unwrap(), "-----BEGIN\nabc\n\n-----END\n");
+        let input = "tls:\n  cert: |\n    {{ cert }}  key: k\n";
+        assert_eq!(
+            ctx.render(input),
+            Ok("tls:\n  cert: |\n    -----BEGIN\nabc\n\n-----END\n  key: k\n".to_owned())
+        );
+        assert_eq!(
+            ctx.with_indent_multiline(true).render(input),
+            Ok("tls:\n  cert: |\n    -----BEGIN\n    abc\n\n    -----END\n  key: k\n".to_owned())
+        );
+    }
+    #[test]
+    fn indentation_uses_the_column_of_each_variable() {
+        let ctx = Context::new()
+            .with_define("a".parse().unwrap(), "1\r\n\r\n2")
+            .with_define("b".parse().unwrap(), vec!["x\ny", "z"])
+            .with_indent_multiline(true);
+        assert_eq!(
+            ctx.render("  {{ a }}\n- {{#each b}}{{ this }},{{/each}}"),
+            Ok("  1\r\n\r\n  2\n- x\n             y,z,".to_owned())
+        );
+    }
+    fn join(args: &[Value<'_>], hash: &Object<'_>) -> Result<Value<'static>> {
+        let sep = hash.property("sep").and_then(Value::as_string);
+        let items = args
+            .iter()
+            .flat_map(|arg| match arg {
+                Value::List(l) => l.iter().collect(),
+                v => vec![v],
+            })
+            .map(|v| v.as_string().map(|s| s.to_string()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::Helper("join only takes strings".to_owned()))?;
+        Ok(items.join(sep.map_or(",", |s| s.as_ref())).into())
+    }
+    #[test]
+    fn helpers_are_called_with_arguments_and_hash() {
+        let ctx = Context::new()
+            .with_helper("join", join)
+            .with_define("a".parse().unwrap(), "x")
+            .with_define("b".parse().unwrap(), vec!["1", "2"]);
+        assert_eq!(
+            ctx.render(r#"{{ join a "y" 3 }} {{ join b sep=" | " }}"#),
+            Ok("x,y,3 1 | 2".to_owned())
+        );
+        assert_eq!(
+            ctx.render("{{ join a.b }}"),
+            Err(Error::MissingVariable("a.b".parse().unwrap()))
+        );
+        assert_eq!(
+            ctx.render("{{ join b.5 }}"),
+            Err(Error::IndexOutOfRange("b.5".parse().unwrap()))
+        );
+        assert_eq!(
+            ctx.clone()
+                .with_missing_policy(MissingPolicy::Placeholder("?".to_owned()))
+                .render("{{ join a c }}"),
+            Ok("x,?".to_owned())
+        );
+    }
+    #[test]
+    fn helper_arguments_are_looked_up_in_scope() {
+        let ctx = Context::new()
+            .with_helper("join", join)
+            .with_define("sep".parse().unwrap(), "-")
+            .with_define(
+                "rows".parse().unwrap(),
+                vec![
+                    Object::new().with_property("cells", vec!["a", "b"]),
+                    Object::new().with_property("cells", vec!["c"]),
+                ],
+            );
+        assert_eq!(
+            ctx.render("{{#each rows}}{{ join @index cells sep=../sep }};{{/each}}"),
+            Ok("0-a-b;1-c;".to_owned())
+        );
+    }
+    #[test]
+    fn helper_failures_are_reported_at_the_call() {
+        let ctx = Context::new()
+            .with_helper("join", join)
+            .with_helper("list", |_, _| Ok(vec!["a"].into()))
+            .with_define("o".parse().unwrap(), Object::new());
+        assert_eq!(
+            ctx.check("{{ nope a }}\n {{ join o }} {{ list x=1 }}")
+                .iter()
+                .map(|d| (d.location(), d.error()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    Location::new(0, 0),
+                    &Error::UnknownHelper("nope".to_owned())
+                ),
+                (
+                    Location::new(1, 1),
+                    &Error::Helper("join only takes strings".to_owned())
+                ),
+                (
+                    Location::new(14, 1),
+                    &Error::Helper("'list' did not return a string".to_owned())
+                ),
+            ]
+        );
+    }
+    #[test]
+    fn unused_defines_includes_helper_arguments() {
+        let ctx = Context::new()
+            .with_define("a".parse().unwrap(), "1")
+            .with_define("b".parse().unwrap(), "2")
+            .with_define("c".parse().unwrap(), "3");
+        assert_eq!(
+            ctx.unused_defines("{{ f a k=b }}"),
+            Ok(vec![Variable::single("c")])
+        );
+    }
+    #[test]
     fn trying_to_expand_an_object_variable_is_an_error() {
         let ctx = Context::new().with_define("a".parse().unwrap(), Object::new());
         assert_eq!(
@@ -308,11 +2032,66 @@ mod tests {
         assert_eq!(ctx.render("{{b}}"), Ok("c".to_owned()));
     }
     #[test]
+    fn rendering_to_io_reports_write_errors() {
+        struct Broken;
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        assert_eq!(
+            ctx.render_to_io("{{ a }}", &mut Broken),
+            Err(Error::Io(io::ErrorKind::BrokenPipe.into()))
+        );
+    }
+    #[test]
+    fn rendering_to_io_stops_at_first_render_error() {
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        let mut output = Vec::new();
+        assert_eq!(
+            ctx.render_to_io("{{ a }} {{ c }} {{ a }}", &mut output),
+            Err(Error::MissingVariable(Variable::single("c")))
+        );
+        assert_eq!(output, b"b ");
+    }
+    #[test]
+    fn deep_merge_merges_objects_and_replaces_everything_else() {
+        let base = Context::new()
+            .with_define("db.host".parse().unwrap(), "localhost")
+            .with_define("db.ports".parse().unwrap(), vec![1, 2])
+            .with_define(
+                "name".parse().unwrap(),
+                Object::new().with_property("a", "b"),
+            );
+        let ctx = base.deep_merge(
+            Context::new()
+                .with_define("db.ports".parse().unwrap(), vec![3])
+                .with_define("db.user".parse().unwrap(), "admin")
+                .with_define("name".parse().unwrap(), "web"),
+        );
+        assert_eq!(
+            ctx.get_value(&Variable::single("db")),
+            Some(
+                &Object::new()
+                    .with_property("host", "localhost")
+                    .with_property("ports", vec![3])
+                    .with_property("user", "admin")
+                    .into()
+            )
+        );
+        assert_eq!(
+            ctx.get_value(&Variable::single("name")),
+            Some(&Value::from("web"))
+        );
+    }
+    #[test]
     fn redundant_definition_panics() {
-
         Context::new()
             .define(Variable::single("a"), "b")
             .define(Variable::single("a"), "c");
-
     }
 }
@@ -0,0 +1,306 @@
+//! Reading variables from `.env` files
+//!
+//! Each line is `KEY=value`, optionally starting with `export`. Lines starting with `#` are
+//! comments, as is anything after a `#` following whitespace in an unquoted value. Values may be
+//! quoted:
+//!
+//! - `'single'` quotes are taken literally
+//! - `"double"` quotes understand `\n`, `\r`, `\t`, `\"`, `\\` and `\$`, and a `\` at the end of a
+//!   line joins it to the next
+//!
+//! Both kinds of quotes may span several lines.
+//!
+//! ```
+//! # use handybars::Context;
+//! let ctx = Context::from_dotenv(
+//!     r#"
+//! ## database
+//! export DB_HOST=localhost # for now
+//! GREETING="hello\nworld"
+//! "#,
+//! )
+//! .unwrap();
+//! assert_eq!(
+//!     ctx.render("{{ DB_HOST }}: {{ GREETING }}"),
+//!     Ok("localhost: hello\nworld".to_owned())
+//! );
+//! ```
+use std::str::Lines;
+
+use crate::{Context, Variable};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Kind of error reported when reading a `.env` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A line which is neither a comment nor `KEY=value`
+    MissingEquals,
+    /// A key which is empty or has characters other than letters, digits, `_` and `.`
+    InvalidKey(String),
+    /// A quoted value which is never closed
+    UnterminatedQuote,
+    /// Something other than a comment after the closing quote of a value
+    TrailingCharacters,
+}
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::MissingEquals => f.write_str("expected KEY=value"),
+            ErrorKind::InvalidKey(key) => write!(f, "invalid key '{key}'"),
+            ErrorKind::UnterminatedQuote => f.write_str("quoted value is never closed"),
+            ErrorKind::TrailingCharacters => {
+                f.write_str("unexpected characters after quoted value")
+            }
+        }
+    }
+}
+
+/// Error reported when reading a `.env` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    line: usize,
+    kind: ErrorKind,
+}
+impl Error {
+    /// What kind of error is this
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+    /// Line the malformed entry starts on, counting from 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} on line {}", self.kind, self.line)
+    }
+}
+impl std::error::Error for Error {}
+
+/// Read the `KEY=value` pairs of a `.env` file, in the order they appear
+///
+/// ```
+/// # use handybars::dotenv;
+/// assert_eq!(
+///     dotenv::parse("A=1\nB='two'"),
+///     Ok(vec![("A".to_owned(), "1".to_owned()), ("B".to_owned(), "two".to_owned())])
+/// );
+/// assert_eq!(
+///     dotenv::parse("A=1\n\nB").unwrap_err().to_string(),
+///     "expected KEY=value on line 3"
+/// );
+/// ```
+pub fn parse(input: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    let mut lines = input.lines();
+    let mut line_no = 0;
+    while let Some(line) = lines.next() {
+        line_no += 1;
+        let error = |kind| Error {
+            line: line_no,
+            kind,
+        };
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line
+            .strip_prefix("export")
+            .filter(|l| l.starts_with([' ', '\t']))
+            .map_or(line, str::trim_start);
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| error(ErrorKind::MissingEquals))?;
+        let key = key.trim_end();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '.'))
+        {
+            return Err(error(ErrorKind::InvalidKey(key.to_owned())));
+        }
+        let value = match rest.trim_start().chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let start = line_no;
+                let rest = &rest.trim_start()[1..];
+                let (value, used) = quoted(quote, rest, &mut lines)
+                    .ok_or_else(|| error(ErrorKind::UnterminatedQuote))?;
+                line_no += used;
+                value.map_err(|kind| Error { line: start, kind })?
+            }
+            _ => unquoted(rest),
+        };
+        vars.push((key.to_owned(), value));
+    }
+    Ok(vars)
+}
+
+/// Value without any comment and surrounding whitespace
+fn unquoted(value: &str) -> String {
+    let end = value
+        .char_indices()
+        .find(|&(i, ch)| ch == '#' && value[..i].ends_with([' ', '\t']))
+        .map_or(value.len(), |(i, _)| i);
+    value[..end].trim().to_owned()
+}
+
+/// Value in `quote`s starting at `rest`, continuing onto further `lines` if needed
+///
+/// Returns `None` if the quote is never closed, otherwise the value and how many extra lines it
+/// used
+fn quoted<'s>(
+    quote: char,
+    mut rest: &'s str,
+    lines: &mut Lines<'s>,
+) -> Option<(Result<String, ErrorKind>, usize)> {
+    let mut value = String::new();
+    let mut used = 0;
+    loop {
+        let mut chars = rest.char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' if quote == '"' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, ch @ ('"' | '\\' | '$'))) => value.push(ch),
+                    Some((_, ch)) => {
+                        value.push('\\');
+                        value.push(ch);
+                    }
+                    // escaped newline, continue on the next line without one
+                    None => {
+                        rest = lines.next()?;
+                        used += 1;
+                        chars = rest.char_indices();
+                    }
+                },
+                ch if ch == quote => {
+                    let after = rest[i + 1..].trim_start();
+                    if !after.is_empty() && !after.starts_with('#') {
+                        return Some((Err(ErrorKind::TrailingCharacters), used));
+                    }
+                    return Some((Ok(value), used));
+                }
+                ch => value.push(ch),
+            }
+        }
+        rest = lines.next()?;
+        used += 1;
+        value.push('\n');
+    }
+}
+
+impl Context<'static> {
+    /// Create a context with the variables of a `.env` file
+    ///
+    /// Names are kept as written, later definitions replace earlier ones. See the
+    /// [`dotenv`](crate::dotenv) module for the format.
+    pub fn from_dotenv(input: &str) -> Result<Self> {
+        let mut ctx = Self::new();
+        for (name, value) in parse(input)? {
+            ctx.define(Variable::from_parts([name]), value);
+        }
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(input: &str) -> Vec<(String, String)> {
+        parse(input).unwrap()
+    }
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_owned(), value.to_owned())
+    }
+    fn err(input: &str) -> (ErrorKind, usize) {
+        let e = parse(input).unwrap_err();
+        (e.kind, e.line)
+    }
+
+    #[test]
+    fn unquoted_values() {
+        assert_eq!(
+            pairs("A=1\n  B = two words  \nC=\nD=a#b\nE=a #comment\nF= #comment\r\nexport G=g\nexport=h"),
+            vec![
+                pair("A", "1"),
+                pair("B", "two words"),
+                pair("C", ""),
+                pair("D", "a#b"),
+                pair("E", "a"),
+                pair("F", ""),
+                pair("G", "g"),
+                pair("export", "h"),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        assert_eq!(
+            pairs("# comment\n\n   \n  # indented\nA=1\n"),
+            vec![pair("A", "1")]
+        );
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(
+            pairs(
+                r#"A='a "b" \n #c' # comment
+B="a 'b' \"c\" \\ \$d \n\t\q"
+C=""
+D="multi
+line"
+E='also
+multi'
+F="joined \
+line"
+"#
+            ),
+            vec![
+                pair("A", r#"a "b" \n #c"#),
+                pair("B", "a 'b' \"c\" \\ $d \n\t\\q"),
+                pair("C", ""),
+                pair("D", "multi\nline"),
+                pair("E", "also\nmulti"),
+                pair("F", "joined line"),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        assert_eq!(err("A=1\n\nB"), (ErrorKind::MissingEquals, 3));
+        assert_eq!(err("A B=1"), (ErrorKind::InvalidKey("A B".to_owned()), 1));
+        assert_eq!(err("=1"), (ErrorKind::InvalidKey("".to_owned()), 1));
+        assert_eq!(err("A=1\nB=\"x\ny\n"), (ErrorKind::UnterminatedQuote, 2));
+        assert_eq!(err("A='x' y"), (ErrorKind::TrailingCharacters, 1));
+        assert_eq!(err("A=\"x\ny\" z\nB=1"), (ErrorKind::TrailingCharacters, 1));
+        // lines used by multi-line values are counted
+        assert_eq!(err("A=\"x\ny\"\nB"), (ErrorKind::MissingEquals, 3));
+        assert_eq!(
+            Error {
+                line: 4,
+                kind: ErrorKind::UnterminatedQuote
+            }
+            .to_string(),
+            "quoted value is never closed on line 4"
+        );
+    }
+
+    #[test]
+    fn context_from_dotenv() {
+        let ctx = Context::from_dotenv("NAME=web\nNAME=api\napp.port=80").unwrap();
+        assert_eq!(
+            ctx.render("{{ NAME }}:{{ [app.port] }}"),
+            Ok("api:80".to_owned())
+        );
+        assert_eq!(Context::from_dotenv("A=1\nB").unwrap_err().line(), 2);
+    }
+}
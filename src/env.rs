@@ -0,0 +1,194 @@
+//! Defining variables from the environment
+use crate::{Context, Object, Variable};
+
+impl Context<'static> {
+    /// Create a context with every environment variable as a variable of the same name
+    ///
+    /// Environment variables which are not valid unicode are skipped.
+    ///
+    /// ```
+    /// # use handybars::Context;
+    /// std::env::set_var("GREETING", "hello");
+    /// assert_eq!(Context::from_env().render("{{ GREETING }}"), Ok("hello".to_owned()));
+    /// ```
+    pub fn from_env() -> Self {
+        Self::from_vars(env_vars())
+    }
+    /// Define an object called `name` with every environment variable as a property
+    ///
+    /// Environment variables which are not valid unicode are skipped.
+    ///
+    /// ```
+    /// # use handybars::Context;
+    /// std::env::set_var("GREETING", "hello");
+    /// let ctx = Context::new().with_env_namespace("env");
+    /// assert_eq!(ctx.render("{{ env.GREETING }}"), Ok("hello".to_owned()));
+    /// ```
+    pub fn define_env_namespace(&mut self, name: &str) -> &mut Self {
+        self.define_namespace(env_vars(), name)
+    }
+    /// Builder version of [`define_env_namespace`](Context::define_env_namespace)
+    pub fn with_env_namespace(mut self, name: &str) -> Self {
+        self.define_env_namespace(name);
+        self
+    }
+    /// Define the environment variables starting with `prefix`, without the prefix and in
+    /// lowercase
+    ///
+    /// With a `separator` names are split into nested variables, so with a prefix of `APP_` and a
+    /// separator of `__`, `APP_DB__HOST` defines `db.host`. Names which would have an empty
+    /// segment, such as `APP_` itself, are skipped.
+    ///
+    /// ```
+    /// # use handybars::Context;
+    /// std::env::set_var("APP_NAME", "web");
+    /// std::env::set_var("APP_DB__HOST", "db.local");
+    /// let ctx = Context::new().with_env_prefix("APP_", Some("__"));
+    /// assert_eq!(ctx.render("{{ name }}: {{ db.host }}"), Ok("web: db.local".to_owned()));
+    /// ```
+    pub fn define_env_prefix(&mut self, prefix: &str, separator: Option<&str>) -> &mut Self {
+        self.define_prefixed(env_vars(), prefix, separator)
+    }
+    /// Builder version of [`define_env_prefix`](Context::define_env_prefix)
+    pub fn with_env_prefix(mut self, prefix: &str, separator: Option<&str>) -> Self {
+        self.define_env_prefix(prefix, separator);
+        self
+    }
+    fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut ctx = Self::new();
+        for (name, value) in vars.into_iter().filter(|(name, _)| !name.is_empty()) {
+            ctx.define(Variable::from_parts([name]), value);
+        }
+        ctx
+    }
+    fn define_namespace(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+        name: &str,
+    ) -> &mut Self {
+        let mut env = Object::new();
+        for (name, value) in vars {
+            env.add_property(name, value);
+        }
+        self.define(Variable::from_parts([name.to_owned()]), env)
+    }
+    fn define_prefixed(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+        prefix: &str,
+        separator: Option<&str>,
+    ) -> &mut Self {
+        let mut vars = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let name = name.strip_prefix(prefix)?.to_lowercase();
+                let segments: Vec<_> = match separator {
+                    Some(sep) if !sep.is_empty() => name.split(sep).map(str::to_owned).collect(),
+                    _ => vec![name],
+                };
+                if segments.iter().any(String::is_empty) {
+                    return None;
+                }
+                Some((segments, value))
+            })
+            .collect::<Vec<_>>();
+        // sorted so that `APP_DB__HOST` always replaces `APP_DB` rather than depending on the
+        // order of the environment
+        vars.sort();
+        for (segments, value) in vars {
+            self.define(Variable::from_parts(segments), value);
+        }
+        self
+    }
+}
+
+/// Environment variables which are valid unicode
+fn env_vars() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Object, Value};
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn every_variable_is_defined_as_written() {
+        let ctx = Context::from_vars(vars(&[("HOME", "/root"), ("a.b", "c"), ("", "empty")]));
+        assert_eq!(
+            ctx.render("{{ HOME }} {{ [a.b] }}"),
+            Ok("/root c".to_owned())
+        );
+        assert_eq!(ctx.get_value(&Variable::single("a")), None);
+    }
+
+    #[test]
+    fn namespace_holds_every_variable() {
+        let mut ctx = Context::new();
+        ctx.define_namespace(vars(&[("HOME", "/root"), ("a.b", "c")]), "env");
+        assert_eq!(
+            ctx.get_value(&Variable::single("env")),
+            Some(
+                &Object::new()
+                    .with_property("HOME", "/root")
+                    .with_property("a.b", "c")
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn prefix_is_stripped_and_names_lowercased() {
+        let mut ctx = Context::new();
+        ctx.define_prefixed(
+            vars(&[("APP_NAME", "web"), ("OTHER", "x"), ("APP_DB__HOST", "h")]),
+            "APP_",
+            None,
+        );
+        assert_eq!(
+            ctx.get_value(&Variable::single("name")),
+            Some(&Value::from("web"))
+        );
+        assert_eq!(
+            ctx.get_value(&Variable::single("db__host")),
+            Some(&Value::from("h"))
+        );
+        assert_eq!(ctx.get_value(&Variable::single("other")), None);
+        assert_eq!(ctx.get_value(&Variable::single("OTHER")), None);
+    }
+
+    #[test]
+    fn separator_creates_nested_variables() {
+        let mut ctx = Context::new();
+        ctx.define_prefixed(
+            vars(&[
+                ("APP_DB__HOST", "h"),
+                ("APP_DB", "replaced"),
+                ("APP_DB__PORT", "1"),
+                ("APP_", "empty"),
+                ("APP___X", "empty segment"),
+                ("APP_Y__", "empty segment"),
+            ]),
+            "APP_",
+            Some("__"),
+        );
+        assert_eq!(
+            ctx.get_value(&Variable::single("db")),
+            Some(
+                &Object::new()
+                    .with_property("host", "h")
+                    .with_property("port", "1")
+                    .into()
+            )
+        );
+        assert_eq!(ctx.get_value(&Variable::single("x")), None);
+        assert_eq!(ctx.get_value(&Variable::single("y")), None);
+    }
+}
@@ -0,0 +1,496 @@
+//! Helpers which are available in every [`Context`](crate::Context)
+//!
+//! These are only included with the `helpers` feature (enabled by default). A helper registered
+//! with [`register_helper`](crate::Context::register_helper) under the same name takes precedence
+//! over the built-in one.
+//!
+//! | Helper                        | Result                                                     |
+//! |-------------------------------|------------------------------------------------------------|
+//! | `upper s`, `lower s`          | `s` in upper or lower case                                 |
+//! | `title s`                     | `s` with the first letter of every word in upper case      |
+//! | `snake_case s`                | `s` as `snake_case`                                        |
+//! | `kebab_case s`                | `s` as `kebab-case`                                        |
+//! | `camel_case s`                | `s` as `camelCase`                                         |
+//! | `trim s`                      | `s` without leading and trailing whitespace                |
+//! | `replace s from to`           | `s` with every `from` replaced by `to`                     |
+//! | `truncate s n suffix=".."`    | the first `n` characters of `s`, `suffix` is added if cut  |
+//! | `pad_left s n char="0"`       | `s` padded on the left to `n` characters, with spaces by default |
+//! | `pad_right s n char="."`      | `s` padded on the right to `n` characters, with spaces by default |
+//! | `repeat s n sep=","`          | `s` repeated `n` times, separated by `sep`                 |
+//! | `len v`                       | number of characters in a string or elements in a list or object |
+//!
+//! ```
+//! # use handybars::Context;
+//! let ctx = Context::new().with_define("name".parse().unwrap(), "HTTPServer config");
+//! assert_eq!(
+//!     ctx.render("{{ snake_case name }} {{ pad_left 7 3 char=\"0\" }}"),
+//!     Ok("http_server_config 007".to_owned())
+//! );
+//! ```
+//!
+//! The functions here can also be registered under a different name.
+//!
+//! `pad_left`, `pad_right` and `repeat` fail rather than produce more than [`MAX_LENGTH`] bytes,
+//! and `repeat` also fails for more than [`MAX_LENGTH`] copies.
+use crate::{context::HelperFn, Error, Object, Value};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Longest string in bytes which `pad_left`, `pad_right` and `repeat` will produce
+pub const MAX_LENGTH: usize = 1 << 20;
+
+/// Built-in helper called `name`, if there is one
+pub(crate) fn builtin(name: &str) -> Option<&'static HelperFn> {
+    let helper: &'static HelperFn = match name {
+        "upper" => &upper,
+        "lower" => &lower,
+        "title" => &title,
+        "snake_case" => &snake_case,
+        "kebab_case" => &kebab_case,
+        "camel_case" => &camel_case,
+        "trim" => &trim,
+        "replace" => &replace,
+        "truncate" => &truncate,
+        "pad_left" => &pad_left,
+        "pad_right" => &pad_right,
+        "repeat" => &repeat,
+        "len" => &len,
+        _ => return None,
+    };
+    Some(helper)
+}
+
+/// Check that `name` was called with `n` arguments
+fn arity(name: &str, args: &[Value<'_>], n: usize) -> Result<()> {
+    if args.len() == n {
+        Ok(())
+    } else {
+        let s = if n == 1 { "" } else { "s" };
+        Err(Error::Helper(format!(
+            "'{name}' takes {n} argument{s} but got {}",
+            args.len()
+        )))
+    }
+}
+/// The argument at `index`, which must be a string
+fn string<'v>(name: &str, args: &'v [Value<'_>], index: usize) -> Result<&'v str> {
+    match &args[index] {
+        Value::String(s) => Ok(s),
+        _ => Err(Error::Helper(format!(
+            "'{name}' argument {} is not a string",
+            index + 1
+        ))),
+    }
+}
+/// The argument at `index`, which must be a non-negative whole number
+fn count(name: &str, args: &[Value<'_>], index: usize) -> Result<usize> {
+    string(name, args, index)?.parse().map_err(|_| {
+        Error::Helper(format!(
+            "'{name}' argument {} is not a whole number",
+            index + 1
+        ))
+    })
+}
+/// Check that the result of `name` is not too long, `None` if computing its length overflowed
+fn check_length(name: &str, len: Option<usize>) -> Result<()> {
+    match len {
+        Some(len) if len <= MAX_LENGTH => Ok(()),
+        _ => Err(Error::Helper(format!(
+            "'{name}' result would be longer than {MAX_LENGTH} bytes"
+        ))),
+    }
+}
+/// The `key=value` argument `key`, which must be a string if it is given
+fn option<'v>(name: &str, hash: &'v Object<'_>, key: &str) -> Result<Option<&'v str>> {
+    match hash.property(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(Error::Helper(format!("'{name}' {key}= is not a string"))),
+    }
+}
+
+/// Apply `f` to the single string argument of `name`
+fn map_string(
+    name: &str,
+    args: &[Value<'_>],
+    f: impl FnOnce(&str) -> String,
+) -> Result<Value<'static>> {
+    arity(name, args, 1)?;
+    Ok(f(string(name, args, 0)?).into())
+}
+
+/// `word` with its first letter in upper case and the rest in lower case
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect()
+    })
+}
+
+/// Split `s` into words for changing its case, e.g. `"HTTPServer-name"` into
+/// `["HTTP", "Server", "name"]`
+///
+/// Words are separated by anything that is not a letter or digit and by changes from lower to
+/// upper case.
+fn words(s: &str) -> Vec<&str> {
+    let chars = s.char_indices().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, &(at, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            words.extend(start.take().map(|start| &s[start..at]));
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|i| chars[i].1);
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_numeric()
+                    || (p.is_uppercase() && next.is_some_and(char::is_lowercase))
+            });
+        match start {
+            None => start = Some(at),
+            Some(start_at) if boundary => {
+                words.push(&s[start_at..at]);
+                start = Some(at);
+            }
+            Some(_) => {}
+        }
+    }
+    words.extend(start.map(|start| &s[start..]));
+    words
+}
+
+/// `upper s`, `s` in upper case
+pub fn upper(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("upper", args, str::to_uppercase)
+}
+/// `lower s`, `s` in lower case
+pub fn lower(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("lower", args, str::to_lowercase)
+}
+/// `title s`, `s` with the first letter of every word in upper case and the rest in lower case
+///
+/// Words are separated by whitespace, which is kept as it is.
+pub fn title(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("title", args, |s| {
+        let mut out = String::with_capacity(s.len());
+        let mut rest = s;
+        while !rest.is_empty() {
+            let space = rest.len() - rest.trim_start().len();
+            out.push_str(&rest[..space]);
+            rest = &rest[space..];
+            let word = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            out.push_str(&capitalize(&rest[..word]));
+            rest = &rest[word..];
+        }
+        out
+    })
+}
+/// `snake_case s`, the words of `s` in lower case separated by `_`
+pub fn snake_case(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("snake_case", args, |s| lower_words(s, "_"))
+}
+/// `kebab_case s`, the words of `s` in lower case separated by `-`
+pub fn kebab_case(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("kebab_case", args, |s| lower_words(s, "-"))
+}
+/// The words of `s` in lower case, separated by `sep`
+fn lower_words(s: &str, sep: &str) -> String {
+    words(s)
+        .into_iter()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+/// `camel_case s`, the words of `s` joined together with all but the first capitalized
+pub fn camel_case(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("camel_case", args, |s| {
+        let mut words = words(s).into_iter();
+        let first = words.next().map(str::to_lowercase).unwrap_or_default();
+        words.fold(first, |out, word| out + &capitalize(word))
+    })
+}
+/// `trim s`, `s` without leading and trailing whitespace
+pub fn trim(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    map_string("trim", args, |s| s.trim().to_owned())
+}
+/// `replace s from to`, `s` with every occurrence of `from` replaced by `to`
+pub fn replace(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    arity("replace", args, 3)?;
+    let from = string("replace", args, 1)?;
+    if from.is_empty() {
+        return Err(Error::Helper("'replace' argument 2 is empty".to_owned()));
+    }
+    let s = string("replace", args, 0)?;
+    Ok(s.replace(from, string("replace", args, 2)?).into())
+}
+/// `truncate s n suffix=".."`, the first `n` characters of `s`
+///
+/// `suffix` (nothing by default) is added if any characters were cut off.
+pub fn truncate(args: &[Value<'_>], hash: &Object<'_>) -> Result<Value<'static>> {
+    arity("truncate", args, 2)?;
+    let s = string("truncate", args, 0)?;
+    let n = count("truncate", args, 1)?;
+    let suffix = option("truncate", hash, "suffix")?.unwrap_or_default();
+    Ok(match s.char_indices().nth(n) {
+        Some((end, _)) => format!("{}{suffix}", &s[..end]),
+        None => s.to_owned(),
+    }
+    .into())
+}
+/// `pad_left s n char=" "`, `s` with `char` added to the start until it is `n` characters long
+pub fn pad_left(args: &[Value<'_>], hash: &Object<'_>) -> Result<Value<'static>> {
+    pad("pad_left", args, hash, |s, padding| padding + s)
+}
+/// `pad_right s n char=" "`, `s` with `char` added to the end until it is `n` characters long
+pub fn pad_right(args: &[Value<'_>], hash: &Object<'_>) -> Result<Value<'static>> {
+    pad("pad_right", args, hash, |s, padding| {
+        s.to_owned() + &padding
+    })
+}
+fn pad(
+    name: &str,
+    args: &[Value<'_>],
+    hash: &Object<'_>,
+    join: impl FnOnce(&str, String) -> String,
+) -> Result<Value<'static>> {
+    arity(name, args, 2)?;
+    let s = string(name, args, 0)?;
+    let n = count(name, args, 1)?;
+    let mut fill = option(name, hash, "char")?.unwrap_or(" ").chars();
+    let (Some(fill), None) = (fill.next(), fill.next()) else {
+        return Err(Error::Helper(format!(
+            "'{name}' char= is not a single character"
+        )));
+    };
+    let padding = n.saturating_sub(s.chars().count());
+    check_length(
+        name,
+        padding
+            .checked_mul(fill.len_utf8())
+            .and_then(|len| len.checked_add(s.len())),
+    )?;
+    Ok(join(s, std::iter::repeat_n(fill, padding).collect()).into())
+}
+/// `repeat s n sep=""`, `s` repeated `n` times with `sep` in between
+pub fn repeat(args: &[Value<'_>], hash: &Object<'_>) -> Result<Value<'static>> {
+    arity("repeat", args, 2)?;
+    let s = string("repeat", args, 0)?;
+    let n = count("repeat", args, 1)?;
+    let sep = option("repeat", hash, "sep")?.unwrap_or_default();
+    check_length(
+        "repeat",
+        s.len()
+            .checked_mul(n)
+            .and_then(|len| len.checked_add(sep.len().checked_mul(n.saturating_sub(1))?)),
+    )?;
+    // an empty string is within the length however often it is repeated
+    if n > MAX_LENGTH {
+        return Err(Error::Helper(format!(
+            "'repeat' count is larger than {MAX_LENGTH}"
+        )));
+    }
+    Ok(vec![s; n].join(sep).into())
+}
+/// `len v`, the number of characters in a string or elements in a list or object
+pub fn len(args: &[Value<'_>], _: &Object<'_>) -> Result<Value<'static>> {
+    arity("len", args, 1)?;
+    Ok(match &args[0] {
+        Value::String(s) => s.chars().count(),
+        Value::List(l) => l.len(),
+        Value::Object(o) => o.values.len(),
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Variable};
+
+    fn call(name: &str, args: &[&str], hash: &[(&str, &str)]) -> Result<Value<'static>> {
+        let args = args.iter().map(|a| Value::from(*a)).collect::<Vec<_>>();
+        let hash = hash
+            .iter()
+            .fold(Object::new(), |o, (k, v)| o.with_property(*k, *v));
+        builtin(name).unwrap()(&args, &hash)
+    }
+    fn ok(s: &str) -> Result<Value<'static>> {
+        Ok(s.to_owned().into())
+    }
+
+    #[test]
+    fn upper_and_lower_change_case() {
+        assert_eq!(call("upper", &["Straße ok"], &[]), ok("STRASSE OK"));
+        assert_eq!(call("lower", &["MiXeD 1"], &[]), ok("mixed 1"));
+    }
+    #[test]
+    fn title_capitalizes_every_word() {
+        assert_eq!(
+            call("title", &["  hello wORLD\tagain "], &[]),
+            ok("  Hello World\tAgain ")
+        );
+        assert_eq!(call("title", &[""], &[]), ok(""));
+    }
+    #[test]
+    fn words_are_split_at_case_changes_and_symbols() {
+        assert_eq!(
+            words("HTTPServer-config_v2Name  x"),
+            vec!["HTTP", "Server", "config", "v2", "Name", "x"]
+        );
+        assert_eq!(words("--"), Vec::<&str>::new());
+    }
+    #[test]
+    fn snake_case_joins_words_with_underscores() {
+        assert_eq!(
+            call("snake_case", &["someHTTP value"], &[]),
+            ok("some_http_value")
+        );
+        assert_eq!(
+            call("snake_case", &["already_snake"], &[]),
+            ok("already_snake")
+        );
+    }
+    #[test]
+    fn kebab_case_joins_words_with_dashes() {
+        assert_eq!(call("kebab_case", &["My App.Name"], &[]), ok("my-app-name"));
+    }
+    #[test]
+    fn camel_case_capitalizes_all_but_the_first_word() {
+        assert_eq!(
+            call("camel_case", &["user_ID-value"], &[]),
+            ok("userIdValue")
+        );
+        assert_eq!(call("camel_case", &["Already"], &[]), ok("already"));
+        assert_eq!(call("camel_case", &[""], &[]), ok(""));
+    }
+    #[test]
+    fn trim_removes_surrounding_whitespace() {
+        assert_eq!(call("trim", &["\n a b \t"], &[]), ok("a b"));
+    }
+    #[test]
+    fn replace_replaces_every_occurrence() {
+        assert_eq!(call("replace", &["a.b.c", ".", "/"], &[]), ok("a/b/c"));
+        assert_eq!(
+            call("replace", &["a", "", "b"], &[]),
+            Err(Error::Helper("'replace' argument 2 is empty".to_owned()))
+        );
+    }
+    #[test]
+    fn truncate_cuts_to_a_number_of_characters() {
+        assert_eq!(call("truncate", &["héllo", "2"], &[]), ok("hé"));
+        assert_eq!(
+            call("truncate", &["hello", "3"], &[("suffix", "...")]),
+            ok("hel...")
+        );
+        assert_eq!(
+            call("truncate", &["hello", "5"], &[("suffix", "...")]),
+            ok("hello")
+        );
+        assert_eq!(
+            call("truncate", &["hello", "-1"], &[]),
+            Err(Error::Helper(
+                "'truncate' argument 2 is not a whole number".to_owned()
+            ))
+        );
+    }
+    #[test]
+    fn pad_fills_up_to_a_width() {
+        assert_eq!(call("pad_left", &["7", "3"], &[("char", "0")]), ok("007"));
+        assert_eq!(call("pad_right", &["ab", "4"], &[]), ok("ab  "));
+        assert_eq!(call("pad_right", &["abcde", "4"], &[]), ok("abcde"));
+        assert_eq!(
+            call("pad_left", &["a", "4"], &[("char", "ab")]),
+            Err(Error::Helper(
+                "'pad_left' char= is not a single character".to_owned()
+            ))
+        );
+    }
+    #[test]
+    fn repeat_joins_copies() {
+        assert_eq!(call("repeat", &["ab", "3"], &[]), ok("ababab"));
+        assert_eq!(call("repeat", &["a", "3"], &[("sep", ", ")]), ok("a, a, a"));
+        assert_eq!(call("repeat", &["a", "0"], &[]), ok(""));
+    }
+    #[test]
+    fn repeat_and_pad_are_limited_in_length() {
+        let too_long = |name: &str| {
+            Err(Error::Helper(format!(
+                "'{name}' result would be longer than {MAX_LENGTH} bytes"
+            )))
+        };
+        let max = MAX_LENGTH.to_string();
+        let over = (MAX_LENGTH + 1).to_string();
+        let huge = usize::MAX.to_string();
+        assert_eq!(
+            call("repeat", &["a", &max], &[]).map(|v| v.as_string().unwrap().len()),
+            Ok(MAX_LENGTH)
+        );
+        assert_eq!(call("repeat", &["a", &over], &[]), too_long("repeat"));
+        assert_eq!(call("repeat", &["ab", &huge], &[]), too_long("repeat"));
+        assert_eq!(
+            call("repeat", &["", &huge], &[("sep", ",")]),
+            too_long("repeat")
+        );
+        assert_eq!(
+            call("repeat", &["", &huge], &[]),
+            Err(Error::Helper(format!(
+                "'repeat' count is larger than {MAX_LENGTH}"
+            )))
+        );
+        assert_eq!(call("pad_left", &["a", &over], &[]), too_long("pad_left"));
+        assert_eq!(
+            call("pad_right", &["a", &huge], &[("char", "é")]),
+            too_long("pad_right")
+        );
+        assert_eq!(
+            call("pad_right", &["a", &max], &[]).map(|v| v.as_string().unwrap().len()),
+            Ok(MAX_LENGTH)
+        );
+    }
+    #[test]
+    fn len_counts_characters_and_elements() {
+        assert_eq!(call("len", &["héllo"], &[]), ok("5"));
+        let ctx = Context::new()
+            .with_define(Variable::single("l"), vec![1, 2, 3])
+            .with_define(Variable::single("o"), Object::new().with_property("a", "b"));
+        assert_eq!(ctx.render("{{ len l }} {{ len o }}"), Ok("3 1".to_owned()));
+    }
+    #[test]
+    fn helpers_check_their_arguments() {
+        assert_eq!(
+            call("upper", &["a", "b"], &[]),
+            Err(Error::Helper(
+                "'upper' takes 1 argument but got 2".to_owned()
+            ))
+        );
+        assert_eq!(
+            call("replace", &["a"], &[]),
+            Err(Error::Helper(
+                "'replace' takes 3 arguments but got 1".to_owned()
+            ))
+        );
+        let ctx = Context::new().with_define(Variable::single("l"), vec!["a"]);
+        assert_eq!(
+            ctx.render("{{ upper l }}"),
+            Err(Error::Helper(
+                "'upper' argument 1 is not a string".to_owned()
+            ))
+        );
+    }
+    #[test]
+    fn registered_helpers_take_precedence() {
+        let ctx = Context::new()
+            .with_helper("upper", |_, _| Ok("custom".into()))
+            .with_helper("shout", upper);
+        assert_eq!(
+            ctx.render(r#"{{ upper "a" }} {{ shout "a" }}"#),
+            Ok("custom A".to_owned())
+        );
+    }
+}
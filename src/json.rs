@@ -0,0 +1,470 @@
+//! Reading values from JSON
+//!
+//! Objects become [`Object`]s and arrays become lists. Strings, numbers and booleans become
+//! strings, with numbers kept exactly as written, and `null` is an empty string.
+//!
+//! ```
+//! # use handybars::Context;
+//! let ctx = Context::from_json(r#"{"db": {"host": "localhost", "port": 5432}}"#).unwrap();
+//! assert_eq!(ctx.render("{{ db.host }}:{{ db.port }}"), Ok("localhost:5432".to_owned()));
+//! ```
+use std::borrow::Cow;
+
+use crate::{parse::Location, Context, Object, Value};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// How deeply arrays and objects may be nested
+const MAX_DEPTH: usize = 128;
+
+/// Kind of error reported when reading JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The input ended in the middle of a value
+    UnexpectedEnd,
+    /// A character which is not valid at this point
+    UnexpectedChar(char),
+    /// A `\` in a string which is not followed by a valid escape
+    InvalidEscape,
+    /// Arrays and objects are nested more than 128 levels deep
+    TooDeep,
+    /// [`Context::from_json`] was given something other than an object
+    NotAnObject,
+}
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::UnexpectedEnd => f.write_str("unexpected end of input"),
+            ErrorKind::UnexpectedChar(ch) => write!(f, "unexpected character {ch:?}"),
+            ErrorKind::InvalidEscape => f.write_str("invalid escape sequence"),
+            ErrorKind::TooDeep => write!(f, "nested more than {MAX_DEPTH} levels deep"),
+            ErrorKind::NotAnObject => f.write_str("expected an object"),
+        }
+    }
+}
+
+/// Error reported when reading JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    location: Location,
+    kind: ErrorKind,
+}
+impl Error {
+    /// What kind of error is this
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+    /// Location in the input that this error occurred
+    pub fn location(&self) -> Location {
+        self.location
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {line} column {col}",
+            self.kind,
+            line = self.location.line + 1,
+            col = self.location.col + 1
+        )
+    }
+}
+impl std::error::Error for Error {}
+
+/// Read a value from JSON
+///
+/// ```
+/// # use handybars::{json, Object, Value};
+/// assert_eq!(
+///     json::from_str(r#"{"ports": [80, 443], "tls": true}"#),
+///     Ok(Object::new()
+///         .with_property("ports", vec!["80", "443"])
+///         .with_property("tls", "true")
+///         .into())
+/// );
+/// assert_eq!(
+///     json::from_str("[1,\n 2,]").unwrap_err().to_string(),
+///     "unexpected character ']' at line 2 column 4"
+/// );
+/// ```
+pub fn from_str(input: &str) -> Result<Value<'static>> {
+    let mut parser = Parser::new(input);
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(ch) => Err(parser.error(ErrorKind::UnexpectedChar(ch))),
+    }
+}
+
+impl Context<'static> {
+    /// Create a context with the properties of a JSON object as its variables
+    ///
+    /// See the [`json`](crate::json) module for how values are converted.
+    pub fn from_json(input: &str) -> Result<Self> {
+        match from_str(input)? {
+            Value::Object(o) => Ok(Self::from_object(o)),
+            _ => {
+                let mut parser = Parser::new(input);
+                parser.skip_whitespace();
+                Err(parser.error(ErrorKind::NotAnObject))
+            }
+        }
+    }
+}
+
+struct Parser<'s> {
+    rest: &'s str,
+    location: Location,
+    depth: usize,
+}
+impl<'s> Parser<'s> {
+    fn new(input: &'s str) -> Self {
+        Self {
+            rest: input,
+            location: Location::zero(),
+            depth: 0,
+        }
+    }
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error {
+            location: self.location,
+            kind,
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.rest = &self.rest[ch.len_utf8()..];
+        if ch == '\n' {
+            self.location.line += 1;
+            self.location.col = 0;
+        } else {
+            self.location.col += 1;
+        }
+        Some(ch)
+    }
+    /// Next character, which must exist
+    fn next(&mut self) -> Result<char> {
+        self.bump()
+            .ok_or_else(|| self.error(ErrorKind::UnexpectedEnd))
+    }
+    /// Consume `expected` or fail without consuming anything
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.peek() {
+            Some(ch) if ch == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(ch) => Err(self.error(ErrorKind::UnexpectedChar(ch))),
+            None => Err(self.error(ErrorKind::UnexpectedEnd)),
+        }
+    }
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|ch| matches!(ch, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.bump();
+        }
+    }
+    fn value(&mut self) -> Result<Value<'static>> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error(ErrorKind::UnexpectedEnd)),
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(self.string()?.into()),
+            Some('t') => self.keyword("true"),
+            Some('f') => self.keyword("false"),
+            Some('n') => self.keyword("null").map(|_| "".into()),
+            Some('-' | '0'..='9') => self.number(),
+            Some(ch) => Err(self.error(ErrorKind::UnexpectedChar(ch))),
+        }
+    }
+    fn nested(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<Value<'static>>,
+    ) -> Result<Value<'static>> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(ErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        let value = f(self)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+    fn keyword(&mut self, word: &'static str) -> Result<Value<'static>> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(word.into())
+    }
+    fn object(&mut self) -> Result<Value<'static>> {
+        self.expect('{')?;
+        let mut object = Object::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(object.into());
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            object.add_property(key, value);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => {}
+                '}' => return Ok(object.into()),
+                ch => return Err(self.unexpected(ch)),
+            }
+        }
+    }
+    fn array(&mut self) -> Result<Value<'static>> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(Value::List(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => {}
+                ']' => return Ok(Value::List(items)),
+                ch => return Err(self.unexpected(ch)),
+            }
+        }
+    }
+    /// Error for `ch`, which has just been consumed
+    fn unexpected(&self, ch: char) -> Error {
+        let mut err = self.error(ErrorKind::UnexpectedChar(ch));
+        // never a newline, those are whitespace
+        err.location.col -= 1;
+        err
+    }
+    fn string(&mut self) -> Result<Cow<'static, str>> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error(ErrorKind::UnexpectedEnd)),
+                Some('"') => {
+                    self.bump();
+                    return Ok(out.into());
+                }
+                Some('\\') => {
+                    let start = self.location;
+                    self.bump();
+                    out.push(self.escape().map_err(|mut e| {
+                        if e.kind == ErrorKind::InvalidEscape {
+                            e.location = start;
+                        }
+                        e
+                    })?);
+                }
+                Some(ch) if (ch as u32) < 0x20 => {
+                    return Err(self.error(ErrorKind::UnexpectedChar(ch)))
+                }
+                Some(ch) => {
+                    self.bump();
+                    out.push(ch);
+                }
+            }
+        }
+    }
+    /// Character for an escape sequence, after the `\`
+    fn escape(&mut self) -> Result<char> {
+        Ok(match self.next()? {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.hex4()?;
+                if (0xD800..0xDC00).contains(&high) {
+                    self.expect('\\')
+                        .and_then(|_| self.expect('u'))
+                        .map_err(|_| self.error(ErrorKind::InvalidEscape))?;
+                    let low = self.hex4()?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(self.error(ErrorKind::InvalidEscape));
+                    }
+                    let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    char::from_u32(code).ok_or_else(|| self.error(ErrorKind::InvalidEscape))?
+                } else {
+                    char::from_u32(high).ok_or_else(|| self.error(ErrorKind::InvalidEscape))?
+                }
+            }
+            _ => return Err(self.error(ErrorKind::InvalidEscape)),
+        })
+    }
+    /// 4 hex digits of a `\u` escape
+    fn hex4(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()?
+                .to_digit(16)
+                .ok_or_else(|| self.error(ErrorKind::InvalidEscape))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+    fn digits(&mut self) -> Result<()> {
+        match self.peek() {
+            Some('0'..='9') => {}
+            Some(ch) => return Err(self.error(ErrorKind::UnexpectedChar(ch))),
+            None => return Err(self.error(ErrorKind::UnexpectedEnd)),
+        }
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            self.bump();
+        }
+        Ok(())
+    }
+    fn number(&mut self) -> Result<Value<'static>> {
+        let start = self.rest;
+        if self.peek() == Some('-') {
+            self.bump();
+        }
+        if self.peek() == Some('0') {
+            self.bump();
+        } else {
+            self.digits()?;
+        }
+        if self.peek() == Some('.') {
+            self.bump();
+            self.digits()?;
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.bump();
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.bump();
+            }
+            self.digits()?;
+        }
+        let len = start.len() - self.rest.len();
+        Ok(Value::from(start[..len].to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err(input: &str) -> (ErrorKind, usize, usize) {
+        let e = from_str(input).unwrap_err();
+        (e.kind, e.location.line, e.location.col)
+    }
+
+    #[test]
+    fn scalars_are_strings() {
+        assert_eq!(from_str(r#""hi""#), Ok("hi".into()));
+        assert_eq!(from_str(" true "), Ok("true".into()));
+        assert_eq!(from_str("false"), Ok("false".into()));
+        assert_eq!(from_str("null"), Ok("".into()));
+        assert_eq!(from_str("-0.50e+3"), Ok("-0.50e+3".into()));
+        assert_eq!(from_str("12"), Ok("12".into()));
+    }
+
+    #[test]
+    fn nested_values() {
+        assert_eq!(
+            from_str(r#"{"a": [{}, [], {"b": null}], "c": {"d": "e"}, "a2": 1}"#),
+            Ok(Object::new()
+                .with_property(
+                    "a",
+                    vec![
+                        Value::from(Object::new()),
+                        Value::List(vec![]),
+                        Object::new().with_property("b", "").into(),
+                    ]
+                )
+                .with_property("c", Object::new().with_property("d", "e"))
+                .with_property("a2", "1")
+                .into())
+        );
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(
+            from_str(r#""a\"\\\/\b\f\n\r\té😀""#),
+            Ok("a\"\\/\u{8}\u{c}\n\r\té😀".into())
+        );
+        assert_eq!(
+            from_str(r#""\x""#).unwrap_err().kind,
+            ErrorKind::InvalidEscape
+        );
+        assert_eq!(err(r#"  "ab\q""#), (ErrorKind::InvalidEscape, 0, 5));
+        assert_eq!(err(r#""\ud83d""#).0, ErrorKind::InvalidEscape);
+        assert_eq!(err(r#""\ud83dA""#).0, ErrorKind::InvalidEscape);
+        assert_eq!(err(r#""\u12"#).0, ErrorKind::UnexpectedEnd);
+        assert_eq!(err("\"a\nb\""), (ErrorKind::UnexpectedChar('\n'), 0, 2));
+    }
+
+    #[test]
+    fn errors_have_line_and_column() {
+        assert_eq!(err(""), (ErrorKind::UnexpectedEnd, 0, 0));
+        assert_eq!(
+            err("{\n  \"a\": 1\n  \"b\": 2\n}"),
+            (ErrorKind::UnexpectedChar('"'), 2, 2)
+        );
+        assert_eq!(err("[1, 2"), (ErrorKind::UnexpectedEnd, 0, 5));
+        assert_eq!(err("{\"a\" 1}"), (ErrorKind::UnexpectedChar('1'), 0, 5));
+        assert_eq!(err("{1: 2}"), (ErrorKind::UnexpectedChar('1'), 0, 1));
+        assert_eq!(err("{\"a\": 1,}"), (ErrorKind::UnexpectedChar('}'), 0, 8));
+        assert_eq!(err("tru"), (ErrorKind::UnexpectedEnd, 0, 3));
+        assert_eq!(err("nul1"), (ErrorKind::UnexpectedChar('1'), 0, 3));
+        assert_eq!(err("01"), (ErrorKind::UnexpectedChar('1'), 0, 1));
+        assert_eq!(err("1."), (ErrorKind::UnexpectedEnd, 0, 2));
+        assert_eq!(err("-x"), (ErrorKind::UnexpectedChar('x'), 0, 1));
+        assert_eq!(err("1e"), (ErrorKind::UnexpectedEnd, 0, 2));
+        assert_eq!(err("é {}"), (ErrorKind::UnexpectedChar('é'), 0, 0));
+        assert_eq!(err("\"é\" x"), (ErrorKind::UnexpectedChar('x'), 0, 4));
+        assert_eq!(
+            from_str("[\n\n  x]").unwrap_err().to_string(),
+            "unexpected character 'x' at line 3 column 3"
+        );
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let ok = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(from_str(&ok).is_ok());
+        let deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        assert_eq!(err(&deep), (ErrorKind::TooDeep, 0, MAX_DEPTH));
+    }
+
+    #[test]
+    fn context_from_json_object() {
+        let ctx = Context::from_json(
+            r#"{"db": {"host": "db.local", "replicas": [1, 2]}, "app.name": "web"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.render("{{ db.host }} {{ db.replicas.1 }} {{ [app.name] }}"),
+            Ok("db.local 2 web".to_owned())
+        );
+        let e = Context::from_json("\n  [1]").unwrap_err();
+        assert_eq!(e.kind(), &ErrorKind::NotAnObject);
+        assert_eq!(e.location(), Location::new(2, 1));
+        assert_eq!(
+            Context::from_json("{").unwrap_err().kind(),
+            &ErrorKind::UnexpectedEnd
+        );
+    }
+}
@@ -6,10 +6,25 @@
 use std::{borrow::Cow, str::FromStr};
 
 mod context;
+pub mod dotenv;
+mod env;
+#[cfg(feature = "helpers")]
+pub mod helpers;
+pub mod json;
 pub mod parse;
+#[cfg(feature = "serde")]
+mod ser;
+mod template;
+#[cfg(feature = "toml")]
+pub mod toml;
 mod value;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 
-pub use context::{Context, Error};
+pub use context::{Context, Diagnostic, Error, MissingPolicy, DEFAULT_MAX_PARTIAL_DEPTH};
+#[cfg(feature = "serde")]
+pub use ser::SerializeError;
+pub use template::Template;
 pub use value::{Object, Value};
 
 use crate::parse::{str_from_utf8, ErrorKind};
@@ -36,6 +51,23 @@ impl VariableInner<'_> {
 ///
 /// A variable is a series of non-empty strings seperated by `.`
 ///
+/// Variables starting with `@` (e.g. `@index`) refer to data provided by blocks such as
+/// `{{#each}}` rather than to values in the [`Context`]. Inside blocks which change the scope
+/// variables starting with `../` are looked up in the scope around the current one.
+///
+/// A segment which is a number selects an element of a [list](Value::List). Index segments may also
+/// be written in brackets, `servers.0.host` is the same as `servers.[0].host`.
+///
+/// Segments containing characters which are not allowed in names, such as `.`, `/` or spaces, can be
+/// quoted with brackets too. The brackets are not part of the segment.
+///
+/// ```
+/// # use handybars::Variable;
+/// let var: Variable = "labels.[app.kubernetes.io/name]".parse().unwrap();
+/// assert_eq!(var, Variable::from_parts(["labels", "app.kubernetes.io/name"]));
+/// assert_eq!(var.to_string(), "labels.[app.kubernetes.io/name]");
+/// ```
+///
 /// The lifetime specifier is used to allow variables
 /// which do not own all of their parts. To get a variable
 /// that _does_ own everything see [`into_owned`](Variable::into_owned)
@@ -71,6 +103,28 @@ impl<'a> Variable<'a> {
             VariableInner::Single(s) => s.len(),
         }
     }
+    /// Each part of the variable path in order
+    fn segments(&self) -> &[VariableEl<'a>] {
+        match &self.inner {
+            VariableInner::Segments(s) => s,
+            VariableInner::Single(s) => std::slice::from_ref(s),
+        }
+    }
+    /// Prefix with a `..` segment, making this refer to the scope around the current one
+    fn into_parent(self) -> Self {
+        let mut segments = vec![Cow::Borrowed("..")];
+        segments.extend(self.segments().iter().cloned());
+        Self::from_segments(segments)
+    }
+    /// Prefix the first segment with `@`, making this a data variable such as `@index`
+    fn into_data(mut self) -> Self {
+        let first = match &mut self.inner {
+            VariableInner::Segments(s) => &mut s[0],
+            VariableInner::Single(s) => s,
+        };
+        *first = Cow::Owned(format!("@{first}"));
+        self
+    }
     #[must_use]
     fn from_segments(segments: Vec<VariableEl<'a>>) -> Self {
         Self {
@@ -85,7 +139,8 @@ impl<'a> Variable<'a> {
     }
     /// Construct a variable out of a single element
     ///
-    /// Panics: If given a string which contains `.` or `var` is an empty string
+    /// Panics: If given a string which contains `.` or `var` is an empty string. Use
+    /// [`from_parts`](Variable::from_parts) for a single element which contains a `.`
     #[must_use]
     pub fn single(var: impl Into<VariableEl<'a>>) -> Self {
         let val = var.into();
@@ -123,7 +178,9 @@ impl<'a> Variable<'a> {
                 ),
             }
         } else {
-            Self::single(fst.unwrap())
+            let fst = fst.unwrap().into();
+            assert!(!fst.is_empty(), "variable part cannot be empty");
+            Self::single_unchecked(fst)
         }
     }
     /// Join together two variables
@@ -156,27 +213,93 @@ impl<'a> Variable<'a> {
         }
     }
 }
+/// Segments which could not be parsed as a plain name are written in brackets
 impl std::fmt::Display for Variable<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.inner {
-            VariableInner::Segments(s) => f.write_str(&s.join(".")),
-            VariableInner::Single(s) => f.write_str(s),
+        let mut segments = self.segments();
+        while let [parent, rest @ ..] = segments {
+            if parent != ".." || rest.is_empty() {
+                break;
+            }
+            f.write_str("../")?;
+            segments = rest;
+        }
+        for (i, seg) in segments.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            // `@` is allowed at the start of data variables
+            let name = seg.strip_prefix('@').filter(|_| i == 0).unwrap_or(seg);
+            if !name.is_empty() && name.bytes().all(parse::is_valid_identifier_ch) {
+                f.write_str(seg)?;
+            } else {
+                write!(f, "[{seg}]")?;
+            }
         }
+        Ok(())
     }
 }
 
+/// Parse a single segment of a variable path, either `name` or a quoted segment like `[0]` or
+/// `[app.kubernetes.io/name]`
+///
+/// Returns the segment and the number of bytes it took up
+fn parse_segment(chars: &[u8]) -> Result<(&str, usize), parse::Error> {
+    let Some(inner) = chars.strip_prefix(b"[") else {
+        let seg = parse::try_parse_variable_segment(chars)?;
+        return Ok((parse::str_from_utf8(seg), seg.len()));
+    };
+    match inner.iter().position(|c| matches!(c, b']' | b'\n')) {
+        Some(0) if inner[0] == b']' => Err(parse::Error::new(
+            (1, 0),
+            parse::ErrorKind::EmptyVariableSegment,
+        )),
+        Some(len) if inner[len] == b']' => Ok((parse::str_from_utf8(&inner[..len]), len + 2)),
+        Some(len) => Err(parse::Error::new(
+            (len + 1, 0),
+            parse::ErrorKind::NewlineInVariableSegment,
+        )),
+        None => Err(parse::Error::new(
+            (0, 0),
+            parse::ErrorKind::InvalidCharacter { token: b'[' },
+        )),
+    }
+}
+
+/// Parse a variable from the start of `s`
+///
+/// Returns the variable and the number of bytes it took up, which may be more than
+/// [`Variable::len`] if it has index segments such as `[0]`.
 fn parse_with_terminator(
     s: &str,
     error_if_invalid: bool,
-) -> Result<Variable<'static>, parse::Error> {
+) -> Result<(Variable<'static>, usize), parse::Error> {
+    if let Some(rest) = s.strip_prefix("../") {
+        return parse_with_terminator(rest, error_if_invalid)
+            .map(|(var, len)| (var.into_parent(), len + 3))
+            .map_err(|e| e.add_offset((3, 0)));
+    }
+    if let Some(rest) = s.strip_prefix('@') {
+        return parse_with_terminator(rest, error_if_invalid)
+            .map(|(var, len)| (var.into_data(), len + 1))
+            .map_err(|e| e.add_offset((1, 0)));
+    }
     let chars = s.as_bytes();
 
     let valid_len = {
         let mut head = 0;
-        while head < chars.len() && {
+        while head < chars.len() {
             let ch = chars[head];
-            parse::is_valid_identifier_ch(ch) || ch as char == ' ' || ch as char == '.'
-        } {
+            if ch == b'[' {
+                // quoted segments may contain anything but a newline, if this one is not closed
+                // then parse_segment reports it
+                if let Some(end) = parse::quoted_segment_len(&chars[head..]) {
+                    head += end;
+                    continue;
+                }
+            } else if !(parse::is_valid_identifier_ch(ch) || matches!(ch, b' ' | b'.' | b']')) {
+                break;
+            }
             head += 1;
         }
         head
@@ -196,11 +319,9 @@ fn parse_with_terminator(
         ));
     }
 
-    match parse::try_parse_variable_segment(chars) {
+    match parse_segment(chars) {
         Err(e) => Err(e),
-        Ok(seg) => {
-            let len = seg.len();
-            let seg_s = parse::str_from_utf8(seg);
+        Ok((seg_s, len)) => {
             #[allow(clippy::blocks_in_conditions)]
             Ok(
                 if {
@@ -220,12 +341,14 @@ fn parse_with_terminator(
                 } {
                     return Err(parse::Error::new((len, 0), parse::ErrorKind::SpaceInPath));
                 } else if len == valid_len {
-                    Variable::single_unchecked(seg_s.to_owned())
+                    (Variable::single_unchecked(seg_s.to_owned()), len)
                 } else {
                     let mut segments = vec![Cow::Owned(seg_s.to_owned())];
-                    let mut head = seg_s.len();
-                    let mut segs = loop {
+                    let mut head = len;
+                    let mut after_dot = false;
+                    let (mut segs, end) = loop {
                         if head == valid_len || chars[head] as char == ' ' {
+                            let end = head;
                             head += chars[head..]
                                 .iter()
                                 .take_while(|v| **v as char == ' ')
@@ -236,7 +359,7 @@ fn parse_with_terminator(
                                     ErrorKind::TooManyVariablesInBlock,
                                 ));
                             }
-                            break segments;
+                            break (segments, end);
                         }
                         if chars[head] as char == '.' {
                             let orig_head = head;
@@ -247,22 +370,30 @@ fn parse_with_terminator(
                                     ErrorKind::EmptyVariableSegment,
                                 ));
                             }
+                            after_dot = true;
                             continue;
                         }
                         assert!(head < s.len());
-                        match parse::try_parse_variable_segment(&chars[head..]) {
+                        if !after_dot {
+                            // segments must be separated by dots, e.g. `a[0]` is not allowed
+                            return Err(parse::Error::new(
+                                (head, 0),
+                                ErrorKind::InvalidCharacter { token: chars[head] },
+                            ));
+                        }
+                        match parse_segment(&chars[head..]) {
                             Err(e) => return Err(e.add_offset((head, 0))),
-                            Ok(seg) => {
-                                let len = seg.len();
-                                segments.push(Cow::Owned(parse::str_from_utf8(seg).to_owned()));
+                            Ok((seg, len)) => {
+                                segments.push(Cow::Owned(seg.to_owned()));
                                 head += len;
+                                after_dot = false;
                             }
                         }
                     };
                     if segs.len() == 1 {
-                        Variable::single_unchecked(segs.pop().unwrap())
+                        (Variable::single_unchecked(segs.pop().unwrap()), end)
                     } else {
-                        Variable::from_segments(segs)
+                        (Variable::from_segments(segs), end)
                     }
                 },
             )
@@ -274,7 +405,7 @@ impl FromStr for Variable<'static> {
     type Err = parse::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        parse_with_terminator(s, true)
+        parse_with_terminator(s, true).map(|(var, _)| var)
     }
 }
 
@@ -346,12 +477,128 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parsing_data_variables_keeps_the_at() {
+        let var = Variable::from_str("@index").unwrap();
+        assert_eq!(var, Variable::single("@index"));
+        assert_eq!(var.len(), 6);
+        assert_eq!(
+            Variable::from_str("@key.a"),
+            Ok(Variable::from_parts(["@key", "a"]))
+        );
+        assert_eq!(
+            Variable::from_str("@"),
+            Err(parse::Error::new(
+                (1, 0),
+                parse::ErrorKind::EmptyVariableSegment
+            ))
+        );
+    }
+
+    #[test]
+    fn parsing_index_segments() {
+        let expected = Variable::from_parts(["servers", "0", "host"]);
+        assert_eq!(Variable::from_str("servers.0.host"), Ok(expected.clone()));
+        assert_eq!(Variable::from_str("servers.[0].host"), Ok(expected));
+        assert_eq!(Variable::from_str("[12]"), Ok(Variable::single("12")));
+        assert_eq!(
+            parse_with_terminator("a.[10] ", false),
+            Ok((Variable::from_parts(["a", "10"]), 6))
+        );
+    }
+
+    #[test]
+    fn parsing_malformed_index_segments_fails() {
+        for (input, err) in [
+            (
+                "a.[]",
+                parse::Error::new((3, 0), ErrorKind::EmptyVariableSegment),
+            ),
+            (
+                "a.[x\ny]",
+                parse::Error::new((4, 0), ErrorKind::InvalidCharacter { token: b'\n' }),
+            ),
+            (
+                "a.[0",
+                parse::Error::new((2, 0), ErrorKind::InvalidCharacter { token: b'[' }),
+            ),
+            (
+                "a[0]",
+                parse::Error::new((1, 0), ErrorKind::InvalidCharacter { token: b'[' }),
+            ),
+            (
+                "a.[0]b",
+                parse::Error::new((5, 0), ErrorKind::InvalidCharacter { token: b'b' }),
+            ),
+        ] {
+            assert_eq!(Variable::from_str(input), Err(err), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn parsing_quoted_segments() {
+        assert_eq!(
+            Variable::from_str("labels.[app.kubernetes.io/name].x"),
+            Ok(Variable::from_parts([
+                "labels",
+                "app.kubernetes.io/name",
+                "x"
+            ]))
+        );
+        assert_eq!(
+            Variable::from_str("[a b].[@x]"),
+            Ok(Variable::from_parts(["a b", "@x"]))
+        );
+        assert_eq!(
+            parse_with_terminator("[}}] ", false),
+            Ok((Variable::single("}}"), 4))
+        );
+    }
+
+    #[test]
+    fn parsing_parent_variables() {
+        assert_eq!(
+            Variable::from_str("../a.b"),
+            Ok(Variable::from_parts(["..", "a", "b"]))
+        );
+        assert_eq!(
+            parse_with_terminator("../../a ", false),
+            Ok((Variable::from_parts(["..", "..", "a"]), 7))
+        );
+        assert_eq!(
+            Variable::from_str("../"),
+            Err(parse::Error::new(
+                (3, 0),
+                parse::ErrorKind::EmptyVariableSegment
+            ))
+        );
+    }
+
+    #[test]
+    fn quoted_segments_are_displayed_in_brackets() {
+        for input in [
+            "a.[b.c].d",
+            "[x y]",
+            "@index",
+            "a.[@b]",
+            "a.0.[ü/ö]",
+            "../../a.b",
+            "../@root",
+        ] {
+            let var = Variable::from_str(input).unwrap();
+            assert_eq!(var.to_string(), input);
+        }
+        assert_eq!(Variable::from_parts(["a.b"]).to_string(), "[a.b]");
+    }
+
     #[test]
     fn parsing_variable_from_path_works() {
         let var: Variable = "x.y".parse().unwrap();
         assert_eq!(var, Variable::from_parts(["x", "y"]));
     }
-    fn run_parsing_variable_test(input: &str) -> (Result<Variable, parse::Error>, Variable) {
+    fn run_parsing_variable_test(
+        input: &str,
+    ) -> (Result<Variable<'_>, parse::Error>, Variable<'_>) {
         let var = Variable::from_str(input);
         let split = input
             .split(' ')
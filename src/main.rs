@@ -1,14 +1,15 @@
 use std::{
     fs::File,
     io::{BufReader, Read},
+    path::Path,
     process::exit,
     str::Split,
 };
 
-use handybars::Context;
+use handybars::{parse, Context};
 
-fn resolve_input(path: Option<&String>) -> Box<dyn Read> {
-    match path.map(|s| s.as_str()) {
+fn resolve_input(path: Option<&str>) -> Box<dyn Read> {
+    match path {
         None | Some("-") => Box::new(std::io::stdin().lock()),
         Some(v) => Box::new(BufReader::new(File::open(v).expect("failed to open input"))),
     }
@@ -49,13 +50,66 @@ fn parse_defines(args: &[String]) -> Vec<(String, String)> {
     }
     out
 }
+/// Values given for `name`, either as `name=value` or `name value`
+fn option_values<'a>(args: &'a [String], name: &str) -> Vec<&'a str> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == name {
+            out.push(
+                args.next()
+                    .unwrap_or_else(|| panic!("expected value after {name}"))
+                    .as_str(),
+            );
+        } else if let Some(value) = arg.strip_prefix(name).and_then(|v| v.strip_prefix('=')) {
+            out.push(value);
+        }
+    }
+    out
+}
+/// Context with the variables defined in the file at `path`, '-' for stdin
+///
+/// The format is picked from the extension, defaulting to JSON
+fn load_data(path: &str) -> Context<'static> {
+    let mut data = String::new();
+    resolve_input(Some(path))
+        .read_to_string(&mut data)
+        .unwrap_or_else(|e| panic!("failed to read data from {path}: {e}"));
+    let ctx = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "toml")]
+        Some("toml") => Context::from_toml(&data).map_err(|e| e.to_string()),
+        #[cfg(not(feature = "toml"))]
+        Some("toml") => Err("handybars was built without the toml feature".to_owned()),
+        #[cfg(feature = "yaml")]
+        Some("yaml" | "yml") => Context::from_yaml(&data).map_err(|e| e.to_string()),
+        #[cfg(not(feature = "yaml"))]
+        Some("yaml" | "yml") => Err("handybars was built without the yaml feature".to_owned()),
+        _ => Context::from_json(&data).map_err(|e| e.to_string()),
+    };
+    ctx.unwrap_or_else(|e| panic!("failed to parse data from {path}: {e}"))
+}
+fn parse_delimiters(value: &str) -> (String, String) {
+    match value.split_whitespace().collect::<Vec<_>>()[..] {
+        [open, close] => (open.to_owned(), close.to_owned()),
+        _ => panic!("expected delimiters of the form 'OPEN CLOSE'"),
+    }
+}
 fn print_usage(path: &str) {
     print!(
         r"handybars - simple template expansion
 
-Usage: {path} [INPUT|-] {{(--define|-D)=varname=value}}*
+Usage: {path} [INPUT|-] {{(--define|-D)=varname=value}}* [--data FILE|-]* [--env] [--env-prefix PREFIX]* [--env-separator SEP] [--env-file FILE]* [--delimiters='OPEN CLOSE'] [--indent]
 
     '-' for INPUT is stdin, if INPUT is not provided it defaults to '-'
+    --data reads variables from a JSON, TOML or YAML file picked by extension, '-' is JSON on stdin
+        later files are merged over earlier ones and defines take precedence over all of them
+    --env makes environment variables available as {{{{ env.NAME }}}}
+    --env-prefix defines environment variables starting with PREFIX, without the prefix and in lowercase,
+        with '__' separating nested names, e.g. --env-prefix APP_ makes APP_DB__HOST {{{{ db.host }}}}
+    --env-separator replaces the '__' used by --env-prefix, --env-separator= turns nesting off
+    --env-file defines the variables in a .env file of KEY=value lines
+    --delimiters replaces '{{{{' and '}}}}', e.g. --delimiters='<% %>'
+    --indent lines up every line of multi-line values with the '{{{{' they replace
 
 e.g.
 > echo '{{ hello.world }}' | {path} - --define hello.world='hello world'
@@ -75,13 +129,53 @@ fn main() {
     } else {
         Vec::new()
     };
+    let data = args
+        .get(2..)
+        .map(|a| option_values(a, "--data"))
+        .unwrap_or_default();
+    if data.contains(&"-") && args.get(1).is_none_or(|i| i == "-") {
+        panic!("stdin cannot be used for both INPUT and --data");
+    }
     let mut ctx = Context::new();
+    for path in data {
+        ctx.deep_append(load_data(path));
+    }
+    if let Some(args) = args.get(2..) {
+        if args.iter().any(|a| a == "--env") {
+            ctx.define_env_namespace("env");
+        }
+        let separator = option_values(args, "--env-separator").pop().unwrap_or("__");
+        for prefix in option_values(args, "--env-prefix") {
+            ctx.define_env_prefix(prefix, Some(separator));
+        }
+        for path in option_values(args, "--env-file") {
+            let env = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read env file {path}: {e}"));
+            ctx.deep_append(
+                Context::from_dotenv(&env)
+                    .unwrap_or_else(|e| panic!("failed to parse env file {path}: {e}")),
+            );
+        }
+    }
+    if let Some(delimiters) = args
+        .get(2..)
+        .and_then(|a| option_values(a, "--delimiters").pop())
+    {
+        let (open, close) = parse_delimiters(delimiters);
+        ctx.set_parse_options(parse::Options::new().delimiters(open, close));
+    }
+    if args
+        .get(2..)
+        .is_some_and(|a| a.iter().any(|a| a == "--indent"))
+    {
+        ctx.set_indent_multiline(true);
+    }
     for (var, val) in defines {
         ctx.define(var.parse().expect("failed to parse define variable"), val);
     }
 
     let mut input = String::new();
-    resolve_input(args.get(1))
+    resolve_input(args.get(1).map(String::as_str))
         .read_to_string(&mut input)
         .expect("failed to read input");
     let output = ctx.render(&input).expect("failed to render template");
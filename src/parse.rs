@@ -1,4 +1,6 @@
 //! Parsing utilities for templates
+use std::borrow::Cow;
+
 use crate::Variable;
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -88,6 +90,29 @@ pub enum ErrorKind {
     },
     /// More than 1 variable in a template (`{{ ... }}`) block
     TooManyVariablesInBlock,
+    /// A template block (`{{ ...`) reached the end of input without being closed
+    ///
+    /// See [`Options::lenient`] to treat these as text instead
+    UnterminatedBlock,
+    /// `{{#name ...}}` where `name` is not a known kind of block
+    UnknownBlock,
+    /// A block (`{{#if ...}}`) reached the end of input without a matching `{{/if}}`
+    UnclosedBlock(BlockKind),
+    /// A closing tag (`{{/if}}`) which does not match the innermost open block
+    UnexpectedBlockClose {
+        /// Kind of the closing tag
+        found: BlockKind,
+        /// Kind of the innermost open block, if there is one
+        expected: Option<BlockKind>,
+    },
+    /// `{{else}}` outside of a block or a second `{{else}}` in the same block
+    UnexpectedElse,
+    /// `{{>}}` without the name of a partial
+    EmptyPartialName,
+    /// A string literal (`"text"`) passed to a helper is not closed on the same line
+    UnterminatedString,
+    /// Blocks (`{{#if ...}}`) nested inside each other more than 128 levels deep
+    TooDeep,
 }
 impl std::fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -101,6 +126,21 @@ impl std::fmt::Display for ErrorKind {
             ErrorKind::TooManyVariablesInBlock => {
                 f.write_str("more than 1 variable in template block")
             }
+            ErrorKind::UnterminatedBlock => f.write_str("unterminated template block"),
+            ErrorKind::UnknownBlock => f.write_str("unknown block"),
+            ErrorKind::UnclosedBlock(kind) => write!(f, "'#{kind}' block is never closed"),
+            ErrorKind::UnexpectedBlockClose {
+                found,
+                expected: Some(expected),
+            } => write!(f, "expected '/{expected}' but found '/{found}'"),
+            ErrorKind::UnexpectedBlockClose {
+                found,
+                expected: None,
+            } => write!(f, "'/{found}' does not close any block"),
+            ErrorKind::UnexpectedElse => f.write_str("'else' outside of a block"),
+            ErrorKind::EmptyPartialName => f.write_str("missing partial name"),
+            ErrorKind::UnterminatedString => f.write_str("unterminated string literal"),
+            ErrorKind::TooDeep => write!(f, "blocks nested more than {MAX_DEPTH} levels deep"),
         }
     }
 }
@@ -215,12 +255,38 @@ pub(crate) fn try_parse_variable_segment(input: &[u8]) -> Result<&[u8]> {
     Ok(input)
 }
 
-fn parse_template_inner(input: &[u8]) -> Option<Result<(Variable, usize)>> {
-    let mut head = 0;
-    while head < input.len() && input[head] as char == ' ' {
-        head += 1;
+/// Parse the inside of a `{{ ... }}` block, `input` starts after the opening delimiter and `end` is
+/// where [`find_close`] found the closing delimiter in it
+///
+/// Returns the variable, the number of bytes consumed including the closing delimiter and whether
+/// the block ended with `~}}`. `None` means this is not a template block and should be treated as
+/// text.
+///
+/// Error offsets are byte offsets from the start of `input`, except for
+/// [`UnterminatedBlock`](ErrorKind::UnterminatedBlock) which has no meaningful offset.
+fn parse_template_inner<'i>(
+    input: &'i [u8],
+    close: &[u8],
+    end: Option<usize>,
+) -> Option<Result<(Variable<'i>, usize, bool)>> {
+    let unterminated = || {
+        Some(Err(Error::new(
+            Location::zero(),
+            ErrorKind::UnterminatedBlock,
+        )))
+    };
+    // The block ends at the first closing delimiter, so the variable parser never sees it
+    let body = &input[..end.unwrap_or(input.len())];
+    let (body, trim) = match body.strip_suffix(b"~") {
+        Some(body) if end.is_some() => (body, true),
+        _ => (body, false),
+    };
+    let skip_spaces = |head: usize| head + body[head..].iter().take_while(|c| **c == b' ').count();
+    let mut head = skip_spaces(0);
+    if head == body.len() {
+        return if end.is_some() { None } else { unterminated() };
     }
-    let var = match super::parse_with_terminator(str_from_utf8(&input[head..]), false) {
+    let (var, len) = match super::parse_with_terminator(str_from_utf8(&body[head..]), false) {
         Ok(v) => v,
         Err(Error {
             ty: ErrorKind::EmptyVariableSegment,
@@ -228,19 +294,323 @@ fn parse_template_inner(input: &[u8]) -> Option<Result<(Variable, usize)>> {
         }) => return None,
         Err(e) => return Some(Err(e.add_offset(Location::new(head, 0)))),
     };
-    head += var.len();
-    fn check_end_condition(head: usize, input: &[u8]) -> bool {
-        input[head] as char == '}' && input[head + 1] as char == '}'
+    head = skip_spaces(head + len);
+    let rest = &body[head..];
+    match end {
+        Some(end) if rest.is_empty() => Some(Ok((var, end + close.len(), trim))),
+        // could be the start of the closing delimiter
+        None if rest.is_empty()
+            || close.starts_with(rest)
+            || (rest[0] == b'~' && close.starts_with(&rest[1..])) =>
+        {
+            unterminated()
+        }
+        _ => Some(Err(Error::new(
+            (head, 0),
+            ErrorKind::InvalidCharacter { token: rest[0] },
+        ))),
+    }
+}
+
+/// Parse a comment block, `input` starts after the opening delimiter and begins with `!`
+///
+/// `{{!-- ... --}}` comments may contain `}}`, `{{! ... }}` comments may not.
+fn parse_comment<'i>(input: &'i [u8], close: &[u8]) -> Result<(Token<'i>, usize, bool)> {
+    let (open, close_prefix) = if input.starts_with(b"!--") {
+        (3, b"--".as_slice())
+    } else {
+        (1, b"".as_slice())
+    };
+    (open..input.len())
+        .find_map(|end| {
+            let rest = input[end..].strip_prefix(close_prefix)?;
+            let (trim, rest) = match rest.strip_prefix(b"~") {
+                Some(rest) => (true, rest),
+                None => (false, rest),
+            };
+            rest.starts_with(close).then(|| {
+                let content = str_from_utf8(&input[open..end]);
+                (
+                    Token::Comment(content.into()),
+                    input.len() - rest.len() + close.len(),
+                    trim,
+                )
+            })
+        })
+        .ok_or_else(|| Error::new(Location::zero(), ErrorKind::UnterminatedBlock))
+}
+
+/// Whether a block starting at `input` (after the opening delimiter and any `~`) is a tag such as
+/// `{{#if x}}`, `{{else}}`, `{{/if}}` or `{{> partial}}` rather than a variable
+fn is_tag(input: &[u8]) -> bool {
+    let input = &input[input.iter().take_while(|c| **c == b' ').count()..];
+    match input {
+        [b'#' | b'/' | b'>', ..] => true,
+        [b'e', b'l', b's', b'e', rest @ ..] => rest
+            .first()
+            .is_none_or(|c| *c != b'.' && !is_valid_identifier_ch(*c)),
+        _ => false,
     }
-    while head < input.len() - 1 {
-        if check_end_condition(head, input) {
-            return Some(Ok((var, head + 2)));
+}
+
+/// Parse a tag, `input` starts after the opening delimiter and [`is_tag`] is true for it
+///
+/// See [`parse_template_inner`] for the meaning of `end` and the return value
+fn parse_tag<'i>(
+    input: &'i [u8],
+    close: &[u8],
+    end: Option<usize>,
+) -> Result<(Token<'i>, usize, bool)> {
+    let end = end.ok_or_else(|| Error::new(Location::zero(), ErrorKind::UnterminatedBlock))?;
+    let (body, trim) = match input[..end].strip_suffix(b"~") {
+        Some(body) => (body, true),
+        None => (&input[..end], false),
+    };
+    let skip_spaces = |head: usize| head + body[head..].iter().take_while(|c| **c == b' ').count();
+    let head = skip_spaces(0);
+    let kind_at = |head: usize| {
+        let len = body[head..]
+            .iter()
+            .take_while(|c| is_valid_identifier_ch(**c))
+            .count();
+        BlockKind::from_name(&body[head..head + len])
+            .map(|kind| (kind, head + len))
+            .ok_or_else(|| Error::new((head, 0), ErrorKind::UnknownBlock))
+    };
+    let (token, rest) = match body[head] {
+        b'#' => {
+            let (kind, head) = kind_at(head + 1)?;
+            let head = skip_spaces(head);
+            let (variable, len) = super::parse_with_terminator(str_from_utf8(&body[head..]), false)
+                .map_err(|e| e.add_offset((head, 0)))?;
+            (Token::BlockOpen { kind, variable }, head + len)
         }
-        head += 1;
+        b'/' => {
+            let (kind, head) = kind_at(head + 1)?;
+            (Token::BlockClose(kind), head)
+        }
+        b'>' => {
+            let head = skip_spaces(head + 1);
+            let len = body[head..]
+                .iter()
+                .take_while(|c| is_valid_identifier_ch(**c) || matches!(c, b'/' | b'.'))
+                .count();
+            if len == 0 {
+                return Err(Error::new((head, 0), ErrorKind::EmptyPartialName));
+            }
+            let name = str_from_utf8(&body[head..head + len]);
+            (Token::Partial(name.into()), head + len)
+        }
+        _ => (Token::Else, head + "else".len()),
+    };
+    let rest = skip_spaces(rest);
+    if rest != body.len() {
+        return Err(Error::new(
+            (rest, 0),
+            ErrorKind::InvalidCharacter { token: body[rest] },
+        ));
+    }
+    Ok((token, end + close.len(), trim))
+}
+
+/// Whether a block starting at `input` (after the opening delimiter and any `~`) calls a helper,
+/// i.e. it is a name followed by at least one argument such as `{{ upper name }}`
+///
+/// Only the block up to `end`, the closing delimiter found by [`find_close`], is looked at.
+fn is_helper_call(input: &[u8], end: Option<usize>) -> bool {
+    let end = end.unwrap_or(input.len());
+    let body = input[..end].strip_suffix(b"~").unwrap_or(&input[..end]);
+    let spaces = |input: &[u8]| input.iter().take_while(|c| **c == b' ').count();
+    let body = &body[spaces(body)..];
+    let name_len = body
+        .iter()
+        .take_while(|c| is_valid_identifier_ch(**c))
+        .count();
+    let rest = &body[name_len..];
+    let args = &rest[spaces(rest)..];
+    name_len > 0 && args.len() < rest.len() && !args.is_empty() && args[0] != b'.'
+}
+
+/// Parse a helper call, `input` starts after the opening delimiter and [`is_helper_call`] is true
+/// for it
+///
+/// See [`parse_template_inner`] for the meaning of `end` and the return value
+fn parse_helper_call<'i>(
+    input: &'i [u8],
+    close: &[u8],
+    end: Option<usize>,
+) -> Result<(Token<'i>, usize, bool)> {
+    let end = end.ok_or_else(|| Error::new(Location::zero(), ErrorKind::UnterminatedBlock))?;
+    let (body, trim) = match input[..end].strip_suffix(b"~") {
+        Some(body) => (body, true),
+        None => (&input[..end], false),
+    };
+    let skip_spaces = |head: usize| head + body[head..].iter().take_while(|c| **c == b' ').count();
+    let ident_len = |head: usize| {
+        body[head..]
+            .iter()
+            .take_while(|c| is_valid_identifier_ch(**c))
+            .count()
+    };
+    let head = skip_spaces(0);
+    let name_len = ident_len(head);
+    let mut call = HelperCall {
+        name: str_from_utf8(&body[head..head + name_len]).into(),
+        args: Vec::new(),
+        hash: Vec::new(),
+    };
+    let mut head = skip_spaces(head + name_len);
+    while head < body.len() {
+        let key_len = ident_len(head);
+        let key = (key_len > 0 && body.get(head + key_len) == Some(&b'='))
+            .then(|| str_from_utf8(&body[head..head + key_len]));
+        let start = key.map_or(head, |key| head + key.len() + 1);
+        let (param, len) = parse_param(&body[start..]).map_err(|e| e.add_offset((start, 0)))?;
+        match key {
+            Some(key) => call.hash.push((key.into(), param)),
+            None => call.args.push(param),
+        }
+        head = start + len;
+        if head < body.len() && body[head] != b' ' {
+            return Err(Error::new(
+                (head, 0),
+                ErrorKind::InvalidCharacter { token: body[head] },
+            ));
+        }
+        head = skip_spaces(head);
+    }
+    Ok((Token::Helper(call), end + close.len(), trim))
+}
+
+/// Parse an argument of a helper call from the start of `input`
+///
+/// Returns the argument and the number of bytes it took up
+fn parse_param(input: &[u8]) -> Result<(Param<'_>, usize)> {
+    if input.starts_with(b"\"") {
+        let len = string_literal_len(input)
+            .ok_or_else(|| Error::new(Location::zero(), ErrorKind::UnterminatedString))?;
+        let value = str_from_utf8(&input[1..len - 1]);
+        return Ok((Param::Literal(value.into()), len));
+    }
+    let mut len = 0;
+    while len < input.len() && input[len] != b' ' {
+        len += quoted_segment_len(&input[len..]).unwrap_or(1);
+    }
+    let word = str_from_utf8(&input[..len]);
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    if digits.starts_with(|c: char| c.is_ascii_digit()) && word.parse::<f64>().is_ok() {
+        return Ok((Param::Literal(word.into()), len));
+    }
+    let (variable, _) = super::parse_with_terminator(word, true)?;
+    Ok((Param::Variable(variable), len))
+}
+
+/// Length of the quoted variable segment (e.g. `[a.b]`) at the start of `input`, including the brackets
+///
+/// `None` if `input` does not start with a `[` or it is not closed on the same line
+pub(crate) fn quoted_segment_len(input: &[u8]) -> Option<usize> {
+    let inner = input.strip_prefix(b"[")?;
+    let len = inner.iter().position(|c| matches!(c, b']' | b'\n'))?;
+    (inner[len] == b']').then_some(len + 2)
+}
+
+/// Length of the string literal (e.g. `"a b"`) at the start of `input`, including the quotes
+///
+/// `None` if `input` does not start with a `"` or it is not closed on the same line
+fn string_literal_len(input: &[u8]) -> Option<usize> {
+    let inner = input.strip_prefix(b"\"")?;
+    let len = inner.iter().position(|c| matches!(c, b'"' | b'\n'))?;
+    (inner[len] == b'"').then_some(len + 2)
+}
+
+/// Offset of the closing delimiter of a block in `input`
+///
+/// Quoted variable segments and string literals are skipped over, since they may contain the
+/// delimiter
+fn find_close(input: &[u8], close: &[u8]) -> Option<usize> {
+    let mut head = 0;
+    while head < input.len() {
+        if input[head..].starts_with(close) {
+            return Some(head);
+        }
+        head += quoted_segment_len(&input[head..])
+            .or_else(|| string_literal_len(&input[head..]))
+            .unwrap_or(1);
     }
     None
 }
 
+/// Whitespace control for a block
+///
+/// A `~` directly after the opening `{{` trims whitespace from the end of the text before the
+/// block and a `~` directly before the closing `}}` trims whitespace from the start of the text
+/// after it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Trim {
+    before: bool,
+    after: bool,
+}
+
+/// Parse any kind of block, `input` starts after the opening delimiter
+///
+/// `closed` is false if `close` does not appear anywhere in `input`, so that unterminated blocks
+/// do not need to search the rest of the input again. See [`parse_template_inner`] for the meaning
+/// of the return value.
+fn parse_block<'i>(
+    input: &'i [u8],
+    close: &[u8],
+    closed: bool,
+) -> Option<Result<(Token<'i>, usize, Trim)>> {
+    let (before, offset) = match input.first() {
+        Some(b'~') => (true, 1),
+        _ => (false, 0),
+    };
+    let input = &input[offset..];
+    // text such as `{{{{` can never be a block, so there is no need to search for its end
+    let first = input.iter().find(|c| **c != b' ');
+    if first.is_some_and(|c| {
+        !(is_valid_identifier_ch(*c)
+            || matches!(c, b'!' | b'#' | b'/' | b'>' | b'[' | b']' | b'.' | b'@'))
+    }) {
+        return None;
+    }
+    let end = closed.then(|| find_close(input, close)).flatten();
+    let block = if input.starts_with(b"!") {
+        Some(if closed {
+            parse_comment(input, close)
+        } else {
+            Err(Error::new(Location::zero(), ErrorKind::UnterminatedBlock))
+        })
+    } else if is_tag(input) {
+        Some(parse_tag(input, close, end))
+    } else if is_helper_call(input, end) {
+        Some(parse_helper_call(input, close, end))
+    } else {
+        parse_template_inner(input, close, end)
+            .map(|r| r.map(|(var, len, after)| (Token::Variable(var), len, after)))
+    };
+    block.map(|r| {
+        r.map(|(token, len, after)| (token, len + offset, Trim { before, after }))
+            .map_err(|e| e.add_offset((offset, 0)))
+    })
+}
+
+/// Move `loc` past `input`
+///
+/// Columns count characters rather than bytes
+fn advance_location(loc: Location, input: &[u8]) -> Location {
+    input.iter().fold(loc, |mut loc, ch| {
+        if *ch == b'\n' {
+            loc.line += 1;
+            loc.col = 0;
+        } else if ch & 0xC0 != 0x80 {
+            // not a utf-8 continuation byte
+            loc.col += 1;
+        }
+        loc
+    })
+}
+
 #[inline]
 pub(crate) fn str_from_utf8(chars: &[u8]) -> &str {
     #[cfg(debug_assertions)]
@@ -256,6 +626,104 @@ pub(crate) fn str_from_utf8(chars: &[u8]) -> &str {
     }
 }
 
+/// Options which control how templates are parsed
+///
+/// ```
+/// # use handybars::parse::*;
+/// let strict = Options::new();
+/// assert!(Tokenize::with_options("{{ a", &strict).next().unwrap().is_err());
+///
+/// let lenient = Options::new().lenient(true);
+/// assert_eq!(
+///     Tokenize::with_options("{{ a", &lenient).next(),
+///     Some(Ok(Token::Str("{{ a".into())))
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    lenient: bool,
+    open: Cow<'static, str>,
+    close: Cow<'static, str>,
+}
+
+static DEFAULT_OPTIONS: Options = Options::new();
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Options {
+    /// Default options
+    pub const fn new() -> Self {
+        Self {
+            lenient: false,
+            open: Cow::Borrowed("{{"),
+            close: Cow::Borrowed("}}"),
+        }
+    }
+    /// Treat blocks which are never closed as text
+    ///
+    /// By default a `{{` without a matching `}}` is an
+    /// [`UnterminatedBlock`](ErrorKind::UnterminatedBlock) error. In lenient mode it is instead
+    /// passed through to the output unchanged.
+    #[must_use]
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+    /// Whether unterminated blocks are treated as text, see [`lenient`](Options::lenient)
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+    /// Use something other than `{{` and `}}` to delimit blocks
+    ///
+    /// Everything else about the syntax stays the same, e.g. comments are `<%! ... %>`, whitespace
+    /// control is `<%~ ... ~%>` and `\<%` is an escaped `<%`.
+    ///
+    /// ```
+    /// # use handybars::{Context, Variable, parse::Options};
+    /// let ctx = Context::new()
+    ///     .with_define(Variable::single("a"), "b")
+    ///     .with_parse_options(Options::new().delimiters("<%", "%>"));
+    /// assert_eq!(ctx.render("{{ a }} <% a %>"), Ok("{{ a }} b".to_owned()));
+    /// ```
+    ///
+    /// Panics: If either delimiter is empty or starts with whitespace, or if `close` starts with an
+    /// ASCII character which can be part of a name, such as a letter, `_` or `$`, since it could
+    /// not be told apart from the end of the name
+    #[must_use]
+    pub fn delimiters(
+        mut self,
+        open: impl Into<Cow<'static, str>>,
+        close: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let (open, close) = (open.into(), close.into());
+        for delim in [&open, &close] {
+            assert!(
+                delim.starts_with(|c: char| !c.is_whitespace()),
+                "delimiters cannot be empty or start with whitespace"
+            );
+        }
+        assert!(
+            !close.starts_with(|c: char| c.is_ascii() && is_valid_identifier_ch(c as u8)),
+            "closing delimiter cannot start with a character allowed in names"
+        );
+        self.open = open;
+        self.close = close;
+        self
+    }
+    /// Delimiter which starts a block
+    pub fn open(&self) -> &str {
+        &self.open
+    }
+    /// Delimiter which ends a block
+    pub fn close(&self) -> &str {
+        &self.close
+    }
+}
+
 /// Tokenization iterator
 ///
 /// This exists to allow true zero-allocation tokenization. See [`tokenize`](crate::parse::tokenize) for
@@ -264,7 +732,31 @@ pub(crate) fn str_from_utf8(chars: &[u8]) -> &str {
 /// ```
 /// # use handybars::{*, parse::*};
 /// let mut tokens = Tokenize::new("some {{ text }}");
-/// assert_eq!(tokens.next(), Some(Ok(Token::Str("some "))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::Str("some ".into()))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::Variable(Variable::single("text")))));
+/// assert_eq!(tokens.next(), None);
+/// ```
+///
+/// A `{{` can be escaped with a `\`, in which case it is returned as text. To put a `\` directly
+/// before a block, escape it with another `\`. In general every pair of `\` directly before a
+/// `{{` is a single literal `\`, and if there is one left over the `{{` is escaped. A `\` anywhere
+/// else is plain text.
+///
+/// ```
+/// # use handybars::{*, parse::*};
+/// let mut tokens = Tokenize::new(r"\{{ text }} \\{{ text }}");
+/// assert_eq!(tokens.next(), Some(Ok(Token::Str("{{ text }} \\".into()))));
+/// assert_eq!(tokens.next(), Some(Ok(Token::Variable(Variable::single("text")))));
+/// ```
+///
+/// Whitespace around a block can be removed by putting a `~` inside its braces, on the side the
+/// whitespace should be removed from. This is applied to the text tokens, which may disappear entirely
+/// if they only contained whitespace.
+///
+/// ```
+/// # use handybars::{*, parse::*};
+/// let mut tokens = Tokenize::new("a: \n  {{~ text ~}}  \n");
+/// assert_eq!(tokens.next(), Some(Ok(Token::Str("a:".into()))));
 /// assert_eq!(tokens.next(), Some(Ok(Token::Variable(Variable::single("text")))));
 /// assert_eq!(tokens.next(), None);
 /// ```
@@ -280,113 +772,643 @@ pub(crate) fn str_from_utf8(chars: &[u8]) -> &str {
 ///
 pub struct Tokenize<'a> {
     chars: &'a [u8],
-    head: usize,
+    open: &'a [u8],
+    close: &'a [u8],
+    lenient: bool,
+    /// Start of the text which has not been emitted yet
     tail: usize,
-    row: usize,
-    col: usize,
+    tail_loc: Location,
+    /// Where we are currently looking for a block
+    head: usize,
+    head_loc: Location,
     hit_error: bool,
-    var_next: Option<Variable<'a>>,
+    /// Start of the last closing delimiter in the input, blocks which start after it are never
+    /// closed
+    last_close: Option<usize>,
+    /// Whether the previous block asked for whitespace at the start of the next text to be trimmed
+    trim_next: bool,
+    next: Option<SourcedToken<'a>>,
 }
 
+/// Token with its location and the text it was parsed from
+///
+/// For blocks the text is the whole block, from the opening `{{` to the closing `}}`
+pub(crate) type SourcedToken<'a> = (Token<'a>, Location, &'a str);
+
 impl<'a> Tokenize<'a> {
     /// Construct a new Tokenize iterator on a given input
     pub fn new(input: &'a str) -> Self {
+        Self::with_options(input, &DEFAULT_OPTIONS)
+    }
+    /// Construct a new Tokenize iterator which parses according to `options`
+    pub fn with_options(input: &'a str, options: &'a Options) -> Self {
+        Self::with_delimiters(input, &options.open, &options.close).lenient(options.lenient)
+    }
+    /// Construct a new Tokenize iterator which uses `open` and `close` instead of `{{` and `}}`
+    ///
+    /// ```
+    /// # use handybars::{*, parse::*};
+    /// let mut tokens = Tokenize::with_delimiters("[[ text ]]", "[[", "]]");
+    /// assert_eq!(tokens.next(), Some(Ok(Token::Variable(Variable::single("text")))));
+    /// ```
+    ///
+    /// See [`Options::delimiters`]
+    pub fn with_delimiters(input: &'a str, open: &'a str, close: &'a str) -> Self {
         Self {
             chars: input.as_bytes(),
-            head: 0,
+            open: open.as_bytes(),
+            close: close.as_bytes(),
+            lenient: false,
             tail: 0,
-            row: 0,
-            col: 0,
+            tail_loc: Location::zero(),
+            head: 0,
+            head_loc: Location::zero(),
             hit_error: false,
-            var_next: None,
+            last_close: (0..=input.len().saturating_sub(close.len()))
+                .rev()
+                .find(|&i| input.as_bytes()[i..].starts_with(close.as_bytes())),
+            trim_next: false,
+            next: None,
         }
     }
-}
+    /// Treat blocks which are never closed as text, see [`Options::lenient`]
+    #[must_use]
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+    /// Also yield the location in the input where each token starts
+    ///
+    /// For variables this is the location of the opening `{{`
+    ///
+    /// ```
+    /// # use handybars::{*, parse::*};
+    /// let mut tokens = Tokenize::new("some\n {{ text }}").spanned();
+    /// assert_eq!(tokens.next(), Some(Ok((Token::Str("some\n ".into()), Location::new(0, 0)))));
+    /// assert_eq!(tokens.next(), Some(Ok((Token::Variable(Variable::single("text")), Location::new(1, 1)))));
+    /// ```
+    pub fn spanned(self) -> Spanned<'a> {
+        Spanned(self)
+    }
 
-impl<'a> Iterator for Tokenize<'a> {
-    type Item = Result<Token<'a>>;
+    /// Location of the byte `offset` bytes past `head`
+    fn location_after(&self, offset: usize) -> Location {
+        let end = (self.head + offset).min(self.chars.len());
+        advance_location(self.head_loc, &self.chars[self.head..end])
+    }
+    fn advance(&mut self, by: usize) {
+        self.head_loc = self.location_after(by);
+        self.head = (self.head + by).min(self.chars.len());
+    }
+    /// Text from `tail` up to `end` with whitespace control applied, `None` if there is nothing left
+    ///
+    /// Does not move `tail`
+    fn take_text(&mut self, end: usize, trim_end: bool) -> Option<SourcedToken<'a>> {
+        let mut text = str_from_utf8(&self.chars[self.tail..end]);
+        let mut loc = self.tail_loc;
+        if std::mem::take(&mut self.trim_next) {
+            let trimmed = text.trim_start();
+            loc = advance_location(loc, &text.as_bytes()[..text.len() - trimmed.len()]);
+            text = trimmed;
+        }
+        if trim_end {
+            text = text.trim_end();
+        }
+        (!text.is_empty()).then(|| (Token::Str(text.into()), loc, text))
+    }
+    /// Emit the pending text (if any) before `token`
+    fn emit_with_text(
+        &mut self,
+        text_end: usize,
+        token: SourcedToken<'a>,
+        trim: Trim,
+    ) -> Option<Result<SourcedToken<'a>>> {
+        let text = self.take_text(text_end, trim.before);
+        self.trim_next = trim.after;
+        self.tail = self.head;
+        self.tail_loc = self.head_loc;
+        if let Some(text) = text {
+            self.next = Some(token);
+            Some(Ok(text))
+        } else {
+            Some(Ok(token))
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(next) = self.var_next.take() {
-            return Some(Ok(Token::Variable(next)));
+    /// Number of `\` directly before `head` in the pending text
+    fn escapes_before_head(&self) -> usize {
+        self.chars[self.tail..self.head]
+            .iter()
+            .rev()
+            .take_while(|ch| **ch == b'\\')
+            .count()
+    }
+
+    pub(crate) fn next_sourced(&mut self) -> Option<Result<SourcedToken<'a>>> {
+        if let Some(next) = self.next.take() {
+            return Some(Ok(next));
         }
         if self.hit_error {
             return None;
-        } else if self.head >= self.chars.len() {
-            if self.tail < self.chars.len() - 1 {
-                let val = Some(Ok(Token::Str(str_from_utf8(&self.chars[self.tail..]))));
-                self.tail = self.chars.len() - 1;
-                return val;
-            }
-            return None;
         }
-
         while self.head < self.chars.len() {
-            let pos = (self.col, self.row);
-            let var = if self.chars[self.head] as char == '{'
-                && self.chars[self.head + 1] as char == '{'
-            {
-                parse_template_inner(&self.chars[self.head + 2..])
-                    .transpose()
-                    .map_err(|e| e.add_offset((pos.0 + 2, pos.1)))
-                    .transpose()
-            } else {
-                None
-            };
-            match var {
-                Some(Ok((var, len))) => {
-                    let prev_tail = self.tail;
-                    let prev_head = self.head;
-                    let should_add_prev = self.tail != self.head;
-                    self.head += len + 2;
-                    self.tail = self.head;
-                    self.col += len + 2;
-                    if should_add_prev {
-                        self.var_next.replace(var);
-                        let val = Some(Ok(Token::Str(str_from_utf8(
-                            &self.chars[prev_tail..prev_head],
-                        ))));
-                        return val;
-                    } else {
-                        return Some(Ok(Token::Variable(var)));
+            if self.chars[self.head..].starts_with(self.open) {
+                let start = self.head;
+                let start_loc = self.head_loc;
+                // each `\\` before a `{{` is a literal `\`, an odd one left over escapes the `{{`
+                let escapes = self.escapes_before_head();
+                let text_end = start - escapes + escapes / 2;
+                if escapes % 2 == 1 {
+                    // `\{{` is a literal `{{`, keep going as text
+                    let text = self.take_text(text_end, false);
+                    self.tail = start;
+                    self.tail_loc = start_loc;
+                    self.advance(self.open.len());
+                    if let Some(text) = text {
+                        return Some(Ok(text));
                     }
+                    continue;
                 }
-                Some(Err(e)) => {
-                    self.hit_error = true;
-                    return Some(Err(e));
-                }
-                None => {
-                    if self.chars[self.head] as char == '\n' {
-                        self.col = 0;
-                        self.row += 1;
-                    } else {
-                        self.col += 1;
+                let body = self.head + self.open.len();
+                let closed = self.last_close.is_some_and(|last| last >= body);
+                match parse_block(&self.chars[body..], self.close, closed) {
+                    Some(Ok((token, len, trim))) => {
+                        self.advance(len + self.open.len());
+                        let source = str_from_utf8(&self.chars[start..self.head]);
+                        return self.emit_with_text(text_end, (token, start_loc, source), trim);
                     }
-                    self.head += 1;
+                    Some(Err(e)) if e.ty == ErrorKind::UnterminatedBlock && self.lenient => {}
+                    Some(Err(e)) => {
+                        self.hit_error = true;
+                        let loc = if e.ty == ErrorKind::UnterminatedBlock {
+                            start_loc
+                        } else {
+                            self.location_after(e.location().col + self.open.len())
+                        };
+                        return Some(Err(Error::new(loc, e.ty)));
+                    }
+                    None => {}
                 }
             }
+            self.advance(1);
         }
-        if self.tail != self.chars.len() - 1 {
-            let val = Some(Ok(Token::Str(str_from_utf8(&self.chars[self.tail..]))));
-            self.tail = self.chars.len() - 1;
-            return val;
+        if self.tail < self.chars.len() {
+            let text = self.take_text(self.chars.len(), false);
+            self.tail = self.chars.len();
+            return text.map(Ok);
         }
         None
     }
 }
 
+impl<'a> Iterator for Tokenize<'a> {
+    type Item = Result<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_sourced().map(|r| r.map(|(token, ..)| token))
+    }
+}
+
+/// Tokenization iterator which includes locations
+///
+/// See [`Tokenize::spanned`]
+pub struct Spanned<'a>(Tokenize<'a>);
+
+impl<'a> Iterator for Spanned<'a> {
+    type Item = Result<(Token<'a>, Location)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next_sourced()
+            .map(|r| r.map(|(token, loc, _)| (token, loc)))
+    }
+}
+
 /// Tokenize an input with allocation
-pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+pub fn tokenize(input: &str) -> Result<Vec<Token<'_>>> {
     Tokenize::new(input).collect()
 }
 
+/// Find every variable referenced by a template
+///
+/// Variables are returned in the order they appear with the location of their `{{`. A variable
+/// which is used more than once is included once for every use. This includes the variables
+/// which blocks such as `{{#if variable}}` depend on.
+///
+/// ```
+/// # use handybars::{Variable, parse::{variables, Location}};
+/// let vars = variables("{{ a.b }} and\n{{ c }}").unwrap();
+/// assert_eq!(
+///     vars,
+///     vec![
+///         (Variable::from_parts(["a", "b"]), Location::new(0, 0)),
+///         (Variable::single("c"), Location::new(0, 1)),
+///     ]
+/// );
+/// ```
+pub fn variables(input: &str) -> Result<Vec<(Variable<'_>, Location)>> {
+    collect_variables(Tokenize::new(input))
+}
+pub(crate) fn collect_variables(tokens: Tokenize<'_>) -> Result<Vec<(Variable<'_>, Location)>> {
+    let mut variables = Vec::new();
+    for token in tokens.spanned() {
+        match token? {
+            (Token::Variable(variable) | Token::BlockOpen { variable, .. }, loc) => {
+                variables.push((variable, loc))
+            }
+            (Token::Helper(call), loc) => {
+                variables.extend(call.into_variables().map(|variable| (variable, loc)))
+            }
+            _ => {}
+        }
+    }
+    Ok(variables)
+}
+
 /// Type for tokens emitted by the parser
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Like [`Variable`], tokens may borrow from the input they were parsed from. Use
+/// [`into_owned`](Token::into_owned) to detach them from it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
 pub enum Token<'a> {
     /// Variable for later expansion
     Variable(Variable<'a>),
     /// Untemplated string input
-    Str(&'a str),
+    Str(Cow<'a, str>),
+    /// Contents of a `{{! ... }}` or `{{!-- ... --}}` comment
+    ///
+    /// Comments are not rendered
+    Comment(Cow<'a, str>),
+    /// Start of a block, e.g. `{{#if variable}}`
+    BlockOpen {
+        #[allow(missing_docs)]
+        kind: BlockKind,
+        /// Variable the block depends on
+        variable: Variable<'a>,
+    },
+    /// `{{else}}`, separates the two branches of a block
+    Else,
+    /// End of a block, e.g. `{{/if}}`
+    BlockClose(BlockKind),
+    /// Name of a partial to include, e.g. `{{> header}}`
+    Partial(Cow<'a, str>),
+    /// Call of a helper, e.g. `{{ upper name }}`
+    Helper(HelperCall<'a>),
+}
+impl Token<'_> {
+    /// Convert a token into one which owns all of its parts
+    #[must_use]
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            Token::Variable(v) => Token::Variable(v.into_owned()),
+            Token::Str(s) => Token::Str(Cow::Owned(s.into_owned())),
+            Token::Comment(s) => Token::Comment(Cow::Owned(s.into_owned())),
+            Token::BlockOpen { kind, variable } => Token::BlockOpen {
+                kind,
+                variable: variable.into_owned(),
+            },
+            Token::Else => Token::Else,
+            Token::BlockClose(kind) => Token::BlockClose(kind),
+            Token::Partial(name) => Token::Partial(Cow::Owned(name.into_owned())),
+            Token::Helper(call) => Token::Helper(call.into_owned()),
+        }
+    }
+}
+
+/// Kind of a block such as `{{#if ...}} ... {{/if}}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BlockKind {
+    /// `{{#if variable}}`, the body is rendered if the variable is
+    /// [truthy](crate::Value::is_truthy)
+    If,
+    /// `{{#unless variable}}`, the body is rendered if the variable is not
+    /// [truthy](crate::Value::is_truthy)
+    Unless,
+    /// `{{#each variable}}`, the body is rendered once for each element of a list or property of
+    /// an object
+    ///
+    /// Inside the body `this` is the current element, `@index` is its position and `@first` and
+    /// `@last` are whether it is the first or last element. When iterating over an object `@key`
+    /// is the name of the property. Any other [truthy](crate::Value::is_truthy) value is treated as
+    /// a list of one element. The else branch is rendered if there are no elements.
+    Each,
+    /// `{{#with variable}}`, the body is rendered with the variable as the innermost scope
+    ///
+    /// Inside the body names are looked up in the variable first and then in the scopes around
+    /// it. `this` is the variable itself, `../name` skips it and looks up `name` in the scope
+    /// around it and `@root.name` looks up `name` in the [`Context`](crate::Context) directly. The
+    /// else branch is rendered if the variable is not [truthy](crate::Value::is_truthy).
+    With,
+}
+impl BlockKind {
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"if" => Some(Self::If),
+            b"unless" => Some(Self::Unless),
+            b"each" => Some(Self::Each),
+            b"with" => Some(Self::With),
+            _ => None,
+        }
+    }
+    /// Name used for the block in templates
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::If => "if",
+            Self::Unless => "unless",
+            Self::Each => "each",
+            Self::With => "with",
+        }
+    }
+}
+impl std::fmt::Display for BlockKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Part of a parsed template
+///
+/// Unlike [`Token`]s, nodes are nested: everything between a block's opening and closing tags is
+/// contained in the [`Block`]. Comments are dropped.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum Node<'a> {
+    /// Untemplated string input
+    Str(Cow<'a, str>),
+    /// Variable for later expansion, with the location of its `{{` and the text of the whole tag
+    Variable(Variable<'a>, Location, Cow<'a, str>),
+    /// Block such as `{{#if variable}} ... {{/if}}`
+    Block(Block<'a>),
+    /// Partial included with `{{> name}}`, with the location of its `{{`
+    Partial(Cow<'a, str>, Location),
+    /// Call of a helper such as `{{ upper name }}`, with the location of its `{{` and the text of
+    /// the whole tag
+    Helper(HelperCall<'a>, Location, Cow<'a, str>),
+}
+impl Node<'_> {
+    /// Convert a node into one which owns all of its parts
+    #[must_use]
+    pub fn into_owned(self) -> Node<'static> {
+        match self {
+            Node::Str(s) => Node::Str(Cow::Owned(s.into_owned())),
+            Node::Variable(v, loc, src) => {
+                Node::Variable(v.into_owned(), loc, Cow::Owned(src.into_owned()))
+            }
+            Node::Block(b) => Node::Block(b.into_owned()),
+            Node::Partial(name, loc) => Node::Partial(Cow::Owned(name.into_owned()), loc),
+            Node::Helper(call, loc, src) => {
+                Node::Helper(call.into_owned(), loc, Cow::Owned(src.into_owned()))
+            }
+        }
+    }
+}
+
+/// Block with its contents, `{{#kind variable}} body {{else}} else_body {{/kind}}`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block<'a> {
+    kind: BlockKind,
+    variable: Variable<'a>,
+    location: Location,
+    body: Vec<Node<'a>>,
+    else_body: Vec<Node<'a>>,
+}
+impl<'a> Block<'a> {
+    /// What kind of block this is
+    pub fn kind(&self) -> BlockKind {
+        self.kind
+    }
+    /// Variable the block depends on
+    pub fn variable(&self) -> &Variable<'a> {
+        &self.variable
+    }
+    /// Location of the `{{` of the opening tag
+    pub fn location(&self) -> Location {
+        self.location
+    }
+    /// Nodes between the opening tag and the `{{else}}` (or the closing tag if there is no `{{else}}`)
+    pub fn body(&self) -> &[Node<'a>] {
+        &self.body
+    }
+    /// Nodes between the `{{else}}` and the closing tag
+    pub fn else_body(&self) -> &[Node<'a>] {
+        &self.else_body
+    }
+    /// Convert a block into one which owns all of its parts
+    #[must_use]
+    pub fn into_owned(self) -> Block<'static> {
+        Block {
+            kind: self.kind,
+            variable: self.variable.into_owned(),
+            location: self.location,
+            body: self.body.into_iter().map(Node::into_owned).collect(),
+            else_body: self.else_body.into_iter().map(Node::into_owned).collect(),
+        }
+    }
+}
+
+/// Argument passed to a helper
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum Param<'a> {
+    /// Variable which is looked up where the helper is called
+    Variable(Variable<'a>),
+    /// String literal (`"text"`) or number, which is passed as a string
+    Literal(Cow<'a, str>),
+}
+impl Param<'_> {
+    /// Convert a parameter into one which owns all of its parts
+    #[must_use]
+    pub fn into_owned(self) -> Param<'static> {
+        match self {
+            Param::Variable(v) => Param::Variable(v.into_owned()),
+            Param::Literal(s) => Param::Literal(Cow::Owned(s.into_owned())),
+        }
+    }
+}
+
+/// Call of a helper, `{{ name arg1 arg2 key=value }}`
+///
+/// ```
+/// # use handybars::{Variable, parse::*};
+/// let tokens = tokenize(r#"{{ default port "80" sep=":" }}"#).unwrap();
+/// let Token::Helper(call) = &tokens[0] else { panic!() };
+/// assert_eq!(call.name(), "default");
+/// assert_eq!(
+///     call.args(),
+///     &[Param::Variable(Variable::single("port")), Param::Literal("80".into())]
+/// );
+/// assert_eq!(call.hash(), &[("sep".into(), Param::Literal(":".into()))]);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HelperCall<'a> {
+    name: Cow<'a, str>,
+    args: Vec<Param<'a>>,
+    hash: Vec<(Cow<'a, str>, Param<'a>)>,
+}
+impl<'a> HelperCall<'a> {
+    /// Name of the helper to call
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Positional arguments, in order
+    pub fn args(&self) -> &[Param<'a>] {
+        &self.args
+    }
+    /// `key=value` arguments, in order
+    pub fn hash(&self) -> &[(Cow<'a, str>, Param<'a>)] {
+        &self.hash
+    }
+    /// Every variable passed to the helper, positional arguments first
+    pub fn variables(&self) -> impl Iterator<Item = &Variable<'a>> {
+        let params = self.args.iter().chain(self.hash.iter().map(|(_, p)| p));
+        params.filter_map(|param| match param {
+            Param::Variable(v) => Some(v),
+            Param::Literal(_) => None,
+        })
+    }
+    pub(crate) fn into_variables(self) -> impl Iterator<Item = Variable<'a>> {
+        let params = self
+            .args
+            .into_iter()
+            .chain(self.hash.into_iter().map(|(_, p)| p));
+        params.filter_map(|param| match param {
+            Param::Variable(v) => Some(v),
+            Param::Literal(_) => None,
+        })
+    }
+    /// Convert a helper call into one which owns all of its parts
+    #[must_use]
+    pub fn into_owned(self) -> HelperCall<'static> {
+        HelperCall {
+            name: Cow::Owned(self.name.into_owned()),
+            args: self.args.into_iter().map(Param::into_owned).collect(),
+            hash: self
+                .hash
+                .into_iter()
+                .map(|(key, param)| (Cow::Owned(key.into_owned()), param.into_owned()))
+                .collect(),
+        }
+    }
+}
+
+/// Parse a template into a tree of [`Node`]s
+///
+/// ```
+/// # use handybars::{Variable, parse::*};
+/// let nodes = parse("{{#if a}}yes{{else}}no{{/if}}").unwrap();
+/// let Node::Block(block) = &nodes[0] else { panic!() };
+/// assert_eq!(block.kind(), BlockKind::If);
+/// assert_eq!(block.variable(), &Variable::single("a"));
+/// assert_eq!(block.body(), &[Node::Str("yes".into())]);
+/// assert_eq!(block.else_body(), &[Node::Str("no".into())]);
+/// ```
+pub fn parse(input: &str) -> Result<Vec<Node<'_>>> {
+    parse_with_options(input, &DEFAULT_OPTIONS)
+}
+/// Parse a template into a tree of [`Node`]s using non-default options
+pub fn parse_with_options<'a>(input: &'a str, options: &'a Options) -> Result<Vec<Node<'a>>> {
+    let (nodes, result) = build_tree(Tokenize::with_options(input, options));
+    result.map(|()| nodes)
+}
+
+/// How deeply blocks may be nested
+const MAX_DEPTH: usize = 128;
+
+/// Block which has been opened but not closed yet
+struct OpenBlock<'a> {
+    block: Block<'a>,
+    in_else: bool,
+}
+impl<'a> OpenBlock<'a> {
+    fn push(&mut self, node: Node<'a>) {
+        if self.in_else {
+            self.block.else_body.push(node);
+        } else {
+            self.block.body.push(node);
+        }
+    }
+}
+
+/// Arrange tokens into a tree, stopping at the first error
+///
+/// The nodes before the error are returned even if there is one, with any blocks which were still
+/// open closed at the point of the error.
+pub(crate) fn build_tree(mut tokens: Tokenize<'_>) -> (Vec<Node<'_>>, Result<()>) {
+    let mut root = Vec::new();
+    let mut open: Vec<OpenBlock<'_>> = Vec::new();
+    let mut result = Ok(());
+    while let Some(token) = tokens.next_sourced() {
+        let node = match token {
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+            Ok((Token::Str(s), ..)) => Node::Str(s),
+            Ok((Token::Variable(var), loc, src)) => Node::Variable(var, loc, src.into()),
+            Ok((Token::Comment(_), ..)) => continue,
+            Ok((Token::Partial(name), loc, _)) => Node::Partial(name, loc),
+            Ok((Token::Helper(call), loc, src)) => Node::Helper(call, loc, src.into()),
+            Ok((Token::BlockOpen { kind, variable }, location, _)) => {
+                if open.len() == MAX_DEPTH {
+                    result = Err(Error::new(location, ErrorKind::TooDeep));
+                    break;
+                }
+                open.push(OpenBlock {
+                    block: Block {
+                        kind,
+                        variable,
+                        location,
+                        body: Vec::new(),
+                        else_body: Vec::new(),
+                    },
+                    in_else: false,
+                });
+                continue;
+            }
+            Ok((Token::Else, loc, _)) => {
+                match open.last_mut() {
+                    Some(block) if !block.in_else => block.in_else = true,
+                    _ => {
+                        result = Err(Error::new(loc, ErrorKind::UnexpectedElse));
+                        break;
+                    }
+                }
+                continue;
+            }
+            Ok((Token::BlockClose(found), loc, _)) => match open.pop() {
+                Some(block) if block.block.kind == found => Node::Block(block.block),
+                block => {
+                    let expected = block.as_ref().map(|b| b.block.kind);
+                    open.extend(block);
+                    result = Err(Error::new(
+                        loc,
+                        ErrorKind::UnexpectedBlockClose { found, expected },
+                    ));
+                    break;
+                }
+            },
+        };
+        match open.last_mut() {
+            Some(block) => block.push(node),
+            None => root.push(node),
+        }
+    }
+    if let (Some(block), Ok(())) = (open.last(), &result) {
+        result = Err(Error::new(
+            block.block.location,
+            ErrorKind::UnclosedBlock(block.block.kind),
+        ));
+    }
+    while let Some(block) = open.pop() {
+        let node = Node::Block(block.block);
+        match open.last_mut() {
+            Some(parent) => parent.push(node),
+            None => root.push(node),
+        }
+    }
+    (root, result)
 }
 
 #[cfg(test)]
@@ -397,7 +1419,7 @@ mod tests {
 
     #[test]
     fn parse_template_inner_errors_with_space_in_path() {
-        let r = parse_template_inner("x .y}}".as_bytes()).unwrap();
+        let r = parse_template_inner("x .y}}".as_bytes(), b"}}", Some(4)).unwrap();
         assert_eq!(r, Err(Error::new((1, 0), ErrorKind::SpaceInPath)));
     }
 
@@ -407,7 +1429,7 @@ mod tests {
         assert_eq!(
             tokens,
             vec![
-                Token::Str("some "),
+                Token::Str("some ".into()),
                 Token::Variable(Variable::single("text"))
             ]
         );
@@ -459,11 +1481,12 @@ export THING=$SOME_VAR";
         assert_eq!(
             tkns.as_slice(),
             &[
-                Token::Str("SOME_VAR="),
+                Token::Str("SOME_VAR=".into()),
                 Token::Variable(Variable::single("t1".to_string())),
                 Token::Str(
                     r"
 export THING=$SOME_VAR"
+                        .into()
                 )
             ]
         )
@@ -473,7 +1496,9 @@ export THING=$SOME_VAR"
     fn parse_template_inner_parses_the_start_of_a_template() {
         let s = "some.txt }}h1";
         let cs = s.as_bytes();
-        let (var, offset) = parse_template_inner(cs).unwrap().unwrap();
+        let (var, offset, _) = parse_template_inner(cs, b"}}", find_close(cs, b"}}"))
+            .unwrap()
+            .unwrap();
         assert_eq!(offset, s.len() - 2, "stops at template end");
         assert_eq!(
             &var,
@@ -486,7 +1511,10 @@ export THING=$SOME_VAR"
         let parsed = tokenize("{{ var }}etc").unwrap();
         assert_eq!(
             parsed.as_slice(),
-            &[Token::Variable(Variable::single("var")), Token::Str("etc")]
+            &[
+                Token::Variable(Variable::single("var")),
+                Token::Str("etc".into())
+            ]
         );
     }
     proptest! {
@@ -494,7 +1522,9 @@ export THING=$SOME_VAR"
         fn parse_template_inner_allows_any_amount_of_whitespace(whitespace in "[ ]*") {
             let s = "test".to_owned() + &whitespace + "}}";
             let cs = s.as_bytes();
-            let (var, _) = parse_template_inner(cs).unwrap().unwrap();
+            let (var, _, _) = parse_template_inner(cs, b"}}", find_close(cs, b"}}"))
+            .unwrap()
+            .unwrap();
             prop_assert_eq!(
                 &var,
                 &Variable::single("test")
@@ -502,6 +1532,678 @@ export THING=$SOME_VAR"
         }
     }
 
+    #[test]
+    fn spanned_tokens_have_start_locations() {
+        let tokens = Tokenize::new("a {{ b }}\n  {{c}}{{ d }}")
+            .spanned()
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Location::new(0, 0),
+                Location::new(2, 0),
+                Location::new(9, 0),
+                Location::new(2, 1),
+                Location::new(7, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_includes_repeated_uses() {
+        let vars = variables("{{ a }}{{ a }}").unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                (Variable::single("a"), Location::new(0, 0)),
+                (Variable::single("a"), Location::new(7, 0))
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_forwards_parse_errors() {
+        assert_eq!(
+            variables("{{ a }} {{ b. }}"),
+            Err(Error::new((12, 0), ErrorKind::EmptyVariableSegment))
+        );
+    }
+
+    #[test]
+    fn tokenizing_keeps_single_trailing_character() {
+        assert_eq!(
+            tokenize("{{ a }}!"),
+            Ok(vec![
+                Token::Variable(Variable::single("a")),
+                Token::Str("!".into())
+            ])
+        );
+    }
+
+    #[test]
+    fn tokenizing_empty_input_gives_no_tokens() {
+        assert_eq!(tokenize(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn tokenizing_truncated_blocks_does_not_panic() {
+        let lenient = Options::new().lenient(true);
+        for input in ["{", "{{", "a{", "a{{", "{{ a", "{{ a }", "{{}}", "}}"] {
+            assert_eq!(
+                Tokenize::with_options(input, &lenient).collect::<Result<Vec<_>>>(),
+                Ok(vec![Token::Str(input.into())]),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn unterminated_blocks_are_errors_at_the_opening_braces() {
+        for (input, loc) in [
+            ("{{", (0, 0)),
+            ("a\n{{ ", (0, 1)),
+            ("a {{ b", (2, 0)),
+            ("{{ a }} {{ a.b }", (8, 0)),
+        ] {
+            assert_eq!(
+                tokenize(input),
+                Err(Error::new(loc, ErrorKind::UnterminatedBlock)),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn lenient_tokenizing_keeps_text_before_unterminated_block() {
+        let lenient = Options::new().lenient(true);
+        assert_eq!(
+            Tokenize::with_options("{{ a }} {{ b", &lenient).collect::<Result<Vec<_>>>(),
+            Ok(vec![
+                Token::Variable(Variable::single("a")),
+                Token::Str(" {{ b".into())
+            ])
+        );
+    }
+
+    #[test]
+    fn lenient_tokenizing_large_unterminated_input_is_linear() {
+        // every `{{` used to search the rest of the input for a closing `}}`
+        let lenient = Options::new().lenient(true);
+        for block in ["{{", "{{ a b ", "{{!", "{{~", "{{#if a "] {
+            let input = block.repeat(50_000);
+            assert_eq!(
+                Tokenize::with_options(&input, &lenient).collect::<Result<Vec<_>>>(),
+                Ok(vec![Token::Str(input.as_str().into())]),
+                "block: {block:?}"
+            );
+        }
+        // nor when the only `}}` is at the very end
+        let input = "{{".repeat(50_000) + "}}";
+        assert_eq!(
+            Tokenize::with_options(&input, &lenient).collect::<Result<Vec<_>>>(),
+            Ok(vec![Token::Str(input.as_str().into())])
+        );
+    }
+
+    #[test]
+    fn escaped_braces_are_text() {
+        assert_eq!(
+            tokenize(r"a \{{ b }} {{ c }}"),
+            Ok(vec![
+                Token::Str("a ".into()),
+                Token::Str("{{ b }} ".into()),
+                Token::Variable(Variable::single("c"))
+            ])
+        );
+        assert_eq!(
+            tokenize(r"\{{ b }}"),
+            Ok(vec![Token::Str("{{ b }}".into())])
+        );
+    }
+
+    #[test]
+    fn escaped_braces_are_never_unterminated() {
+        assert_eq!(tokenize(r"\{{ x"), Ok(vec![Token::Str("{{ x".into())]));
+    }
+
+    #[test]
+    fn escaped_backslash_before_block_is_kept() {
+        assert_eq!(
+            tokenize(r"a\\{{ b }}"),
+            Ok(vec![
+                Token::Str(r"a\".into()),
+                Token::Variable(Variable::single("b"))
+            ])
+        );
+    }
+
+    #[test]
+    fn backslashes_before_a_block_pair_up() {
+        let render = |input: &str| {
+            tokenize(input).map(|tokens| {
+                tokens
+                    .iter()
+                    .map(|t| match t {
+                        Token::Str(s) => s.to_string(),
+                        Token::Variable(v) => format!("<{v}>"),
+                        t => panic!("unexpected {t:?}"),
+                    })
+                    .collect::<String>()
+            })
+        };
+        // even: half as many backslashes and a real block
+        assert_eq!(render(r"a\\\\{{ b }}"), Ok(r"a\\<b>".to_owned()));
+        assert_eq!(render(r"a\\\\\\\\{{ b }}"), Ok(r"a\\\\<b>".to_owned()));
+        // odd: half as many (rounded down) and an escaped block
+        assert_eq!(render(r"a\\\{{ b }}"), Ok(r"a\{{ b }}".to_owned()));
+        assert_eq!(render(r"a\\\\\{{ b }}"), Ok(r"a\\{{ b }}".to_owned()));
+        // backslashes which are not before a block are left alone
+        assert_eq!(render(r"\\\ {{ b }}\\"), Ok(r"\\\ <b>\\".to_owned()));
+    }
+
+    #[test]
+    fn escaped_braces_keep_locations() {
+        let tokens = Tokenize::new("\\{{ a }}\n{{ b }}")
+            .spanned()
+            .map(|t| t.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Str("{{ a }}\n".into()), Location::new(1, 0)),
+                (Token::Variable(Variable::single("b")), Location::new(0, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_tokenized() {
+        assert_eq!(
+            tokenize("a{{! note }}b{{!-- {{ x }} --}}"),
+            Ok(vec![
+                Token::Str("a".into()),
+                Token::Comment(" note ".into()),
+                Token::Str("b".into()),
+                Token::Comment(" {{ x }} ".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn multiline_comments_keep_locations() {
+        let tokens = Tokenize::new("{{!-- one\ntwo\n--}} {{ a }}\n{{b}}")
+            .spanned()
+            .map(|t| t.unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Location::new(0, 0),
+                Location::new(4, 2),
+                Location::new(5, 2),
+                Location::new(12, 2),
+                Location::new(0, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_comments_are_errors() {
+        assert_eq!(
+            tokenize("a {{!-- b }}"),
+            Err(Error::new((2, 0), ErrorKind::UnterminatedBlock))
+        );
+        assert_eq!(
+            Tokenize::with_options("{{! b", &Options::new().lenient(true))
+                .collect::<Result<Vec<_>>>(),
+            Ok(vec![Token::Str("{{! b".into())])
+        );
+    }
+
+    #[test]
+    fn tilde_trims_whitespace_next_to_blocks() {
+        assert_eq!(
+            tokenize("a \n {{~ b }} c {{ d ~}}\n\t e"),
+            Ok(vec![
+                Token::Str("a".into()),
+                Token::Variable(Variable::single("b")),
+                Token::Str(" c ".into()),
+                Token::Variable(Variable::single("d")),
+                Token::Str("e".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn tilde_trimming_drops_whitespace_only_text() {
+        assert_eq!(
+            tokenize("{{ a ~}} \n {{~ b }}  {{~! c ~}}\n"),
+            Ok(vec![
+                Token::Variable(Variable::single("a")),
+                Token::Variable(Variable::single("b")),
+                Token::Comment(" c ".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn tilde_trimming_applies_to_long_comments() {
+        assert_eq!(
+            tokenize("a {{~!-- }} --~}} b"),
+            Ok(vec![
+                Token::Str("a".into()),
+                Token::Comment(" }} ".into()),
+                Token::Str("b".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn tilde_trimming_moves_text_location() {
+        let tokens = Tokenize::new("{{ a ~}}\n  b")
+            .spanned()
+            .map(|t| t.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(tokens[1], (Token::Str("b".into()), Location::new(2, 1)));
+    }
+
+    #[test]
+    fn tilde_without_close_is_unterminated() {
+        assert_eq!(
+            tokenize("{{~ a ~}"),
+            Err(Error::new((0, 0), ErrorKind::UnterminatedBlock))
+        );
+    }
+
+    #[test]
+    fn custom_delimiters_support_all_block_syntax() {
+        let options = Options::new().delimiters("<%", "%>");
+        let tokens = Tokenize::with_options("{{ a }}<%~ b ~%> <%! c %>\\<% d %>", &options)
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            tokens,
+            Ok(vec![
+                Token::Str("{{ a }}".into()),
+                Token::Variable(Variable::single("b")),
+                Token::Comment(" c ".into()),
+                Token::Str("<% d %>".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn custom_delimiters_can_be_multibyte_and_asymmetric() {
+        let tokens = Tokenize::with_delimiters("«a.b» ${c} «!x»", "«", "»")
+            .spanned()
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(
+            tokens,
+            Ok(vec![
+                (
+                    Token::Variable(Variable::from_parts(["a", "b"])),
+                    Location::new(0, 0)
+                ),
+                (Token::Str(" ${c} ".into()), Location::new(5, 0)),
+                (Token::Comment("x".into()), Location::new(11, 0)),
+            ])
+        );
+        assert_eq!(
+            Tokenize::with_delimiters("${ a }", "${", "}").collect::<Result<Vec<_>>>(),
+            Ok(vec![Token::Variable(Variable::single("a"))])
+        );
+    }
+
+    #[test]
+    fn custom_delimiters_detect_unterminated_blocks() {
+        for input in ["a <<", "a << b", "a << b >", "a << b ~>"] {
+            assert_eq!(
+                Tokenize::with_delimiters(input, "<<", ">>").collect::<Result<Vec<_>>>(),
+                Err(Error::new((2, 0), ErrorKind::UnterminatedBlock)),
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn helper_calls_end_at_the_closing_delimiter() {
+        // the bytes of `»` are allowed in names, so `a»` must not be read as a helper name
+        assert_eq!(
+            Tokenize::with_delimiters("«a» b»", "«", "»").collect::<Result<Vec<_>>>(),
+            Ok(vec![
+                Token::Variable(Variable::single("a")),
+                Token::Str(" b»".into()),
+            ])
+        );
+        assert_eq!(
+            Tokenize::with_delimiters("«upper a ~»", "«", "»").collect::<Result<Vec<_>>>(),
+            Ok(vec![Token::Helper(HelperCall {
+                name: "upper".into(),
+                args: vec![Param::Variable(Variable::single("a"))],
+                hash: Vec::new(),
+            })])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn closing_delimiters_which_look_like_names_are_rejected() {
+        let _ = Options::new().delimiters("$", "$");
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_delimiters_are_rejected() {
+        let _ = Options::new().delimiters("", "}}");
+    }
+
+    #[test]
+    fn block_tags_are_tokenized() {
+        let tokens = Tokenize::new("{{#if a.b}}x{{ else }}y{{~/if~}} {{ elsewhere }}").spanned();
+        assert_eq!(
+            tokens.collect::<Result<Vec<_>>>(),
+            Ok(vec![
+                (
+                    Token::BlockOpen {
+                        kind: BlockKind::If,
+                        variable: Variable::from_parts(["a", "b"])
+                    },
+                    Location::new(0, 0)
+                ),
+                (Token::Str("x".into()), Location::new(11, 0)),
+                (Token::Else, Location::new(12, 0)),
+                (Token::Str("y".into()), Location::new(22, 0)),
+                (Token::BlockClose(BlockKind::If), Location::new(23, 0)),
+                (
+                    Token::Variable(Variable::single("elsewhere")),
+                    Location::new(33, 0)
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn malformed_block_tags_are_errors() {
+        for (input, err) in [
+            ("{{#for a}}", Error::new((3, 0), ErrorKind::UnknownBlock)),
+            ("{{/}}", Error::new((3, 0), ErrorKind::UnknownBlock)),
+            (
+                "{{#if}}",
+                Error::new((5, 0), ErrorKind::EmptyVariableSegment),
+            ),
+            (
+                "{{#if a b}}",
+                Error::new((8, 0), ErrorKind::TooManyVariablesInBlock),
+            ),
+            (
+                "{{/if a}}",
+                Error::new((6, 0), ErrorKind::InvalidCharacter { token: b'a' }),
+            ),
+            (
+                "{{else x}}",
+                Error::new((7, 0), ErrorKind::InvalidCharacter { token: b'x' }),
+            ),
+            (
+                "x {{#if a",
+                Error::new((2, 0), ErrorKind::UnterminatedBlock),
+            ),
+        ] {
+            assert_eq!(tokenize(input), Err(err), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn blocks_are_parsed_into_a_tree() {
+        let nodes = parse("a{{#if x}}b{{#unless y}}{{ c }}{{/unless}}{{else}}d{{/if}}");
+        assert_eq!(
+            nodes,
+            Ok(vec![
+                Node::Str("a".into()),
+                Node::Block(Block {
+                    kind: BlockKind::If,
+                    variable: Variable::single("x"),
+                    location: Location::new(1, 0),
+                    body: vec![
+                        Node::Str("b".into()),
+                        Node::Block(Block {
+                            kind: BlockKind::Unless,
+                            variable: Variable::single("y"),
+                            location: Location::new(11, 0),
+                            body: vec![Node::Variable(
+                                Variable::single("c"),
+                                Location::new(24, 0),
+                                "{{ c }}".into()
+                            )],
+                            else_body: vec![],
+                        }),
+                    ],
+                    else_body: vec![Node::Str("d".into())],
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn deeply_nested_blocks_are_errors() {
+        let nested = |depth: usize| "{{#if a}}".repeat(depth) + &"{{/if}}".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH + 1)),
+            Err(Error::new((MAX_DEPTH * 9, 0), ErrorKind::TooDeep))
+        );
+        // would overflow the stack when rendered without the limit
+        assert_eq!(
+            parse(&nested(100_000)),
+            Err(Error::new((MAX_DEPTH * 9, 0), ErrorKind::TooDeep))
+        );
+    }
+
+    #[test]
+    fn mismatched_blocks_are_errors() {
+        for (input, err) in [
+            (
+                "{{#if a}}\n{{#unless b}}",
+                Error::new((0, 1), ErrorKind::UnclosedBlock(BlockKind::Unless)),
+            ),
+            (
+                "{{#if a}}{{/unless}}",
+                Error::new(
+                    (9, 0),
+                    ErrorKind::UnexpectedBlockClose {
+                        found: BlockKind::Unless,
+                        expected: Some(BlockKind::If),
+                    },
+                ),
+            ),
+            (
+                "{{/if}}",
+                Error::new(
+                    (0, 0),
+                    ErrorKind::UnexpectedBlockClose {
+                        found: BlockKind::If,
+                        expected: None,
+                    },
+                ),
+            ),
+            ("a {{else}}", Error::new((2, 0), ErrorKind::UnexpectedElse)),
+            (
+                "{{#if a}}{{else}}{{else}}{{/if}}",
+                Error::new((17, 0), ErrorKind::UnexpectedElse),
+            ),
+        ] {
+            assert_eq!(parse(input), Err(err), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn partial_tags_are_tokenized() {
+        assert_eq!(
+            tokenize("{{> header}}\n{{>layouts/base.html~}} {{ >a}}"),
+            Ok(vec![
+                Token::Partial("header".into()),
+                Token::Str("\n".into()),
+                Token::Partial("layouts/base.html".into()),
+                Token::Partial("a".into()),
+            ])
+        );
+        assert_eq!(
+            parse("{{#if a}}{{> b}}{{/if}}").unwrap()[0],
+            Node::Block(Block {
+                kind: BlockKind::If,
+                variable: Variable::single("a"),
+                location: Location::new(0, 0),
+                body: vec![Node::Partial("b".into(), Location::new(9, 0))],
+                else_body: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_partial_tags_are_errors() {
+        for (input, err) in [
+            ("{{>}}", Error::new((3, 0), ErrorKind::EmptyPartialName)),
+            ("{{>  ~}}", Error::new((5, 0), ErrorKind::EmptyPartialName)),
+            (
+                "{{> a b}}",
+                Error::new((6, 0), ErrorKind::InvalidCharacter { token: b'b' }),
+            ),
+            ("{{> a", Error::new((0, 0), ErrorKind::UnterminatedBlock)),
+        ] {
+            assert_eq!(tokenize(input), Err(err), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn helper_calls_are_tokenized() {
+        let call = |name: &str, args: Vec<Param<'static>>, hash: Vec<(&str, Param<'static>)>| {
+            Token::Helper(HelperCall {
+                name: name.to_owned().into(),
+                args,
+                hash: hash
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned().into(), v))
+                    .collect(),
+            })
+        };
+        assert_eq!(
+            tokenize(r#"{{ upper a.b }}{{~join  items sep=", }}" -1.5 [x y]~}}{{ a }}"#),
+            Ok(vec![
+                call(
+                    "upper",
+                    vec![Param::Variable(Variable::from_parts(["a", "b"]))],
+                    vec![]
+                ),
+                call(
+                    "join",
+                    vec![
+                        Param::Variable(Variable::single("items")),
+                        Param::Literal("-1.5".into()),
+                        Param::Variable(Variable::single("x y")),
+                    ],
+                    vec![("sep", Param::Literal(", }}".into()))]
+                ),
+                Token::Variable(Variable::single("a")),
+            ])
+        );
+    }
+
+    #[test]
+    fn malformed_helper_calls_are_errors() {
+        for (input, err) in [
+            (
+                r#"{{ f "a }}"#,
+                Error::new((5, 0), ErrorKind::UnterminatedString),
+            ),
+            (
+                r#"{{ f "a"b }}"#,
+                Error::new((8, 0), ErrorKind::InvalidCharacter { token: b'b' }),
+            ),
+            (
+                "{{ f k= }}",
+                Error::new((7, 0), ErrorKind::EmptyVariableSegment),
+            ),
+            (
+                "{{ f a. }}",
+                Error::new((6, 0), ErrorKind::EmptyVariableSegment),
+            ),
+            // not a helper call, so still a single variable
+            ("{{ f .a }}", Error::new((4, 0), ErrorKind::SpaceInPath)),
+            (
+                "{{ f.g a }}",
+                Error::new((7, 0), ErrorKind::TooManyVariablesInBlock),
+            ),
+        ] {
+            assert_eq!(tokenize(input), Err(err), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn quoted_segments_may_contain_delimiters() {
+        assert_eq!(
+            tokenize("{{ a.[x}}y] }}{{#if [b c]}}{{/if}}"),
+            Ok(vec![
+                Token::Variable(Variable::from_parts(["a", "x}}y"])),
+                Token::BlockOpen {
+                    kind: BlockKind::If,
+                    variable: Variable::single("b c")
+                },
+                Token::BlockClose(BlockKind::If),
+            ])
+        );
+    }
+
+    #[test]
+    fn junk_after_variable_in_block_is_an_error() {
+        assert_eq!(
+            tokenize("{{ a !}}"),
+            Err(Error::new(
+                (5, 0),
+                ErrorKind::InvalidCharacter { token: b'!' }
+            ))
+        );
+    }
+
+    #[test]
+    fn error_locations_count_characters_and_lines() {
+        assert_eq!(
+            tokenize("é\nü {{ a. }}"),
+            Err(Error::new((6, 1), ErrorKind::EmptyVariableSegment))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn tokenizing_any_input_is_total(input in "\\PC*") {
+            let _ = tokenize(&input);
+        }
+        #[test]
+        fn tokenizing_brace_heavy_input_is_total(input in "[{} a-z.\n!~#/\\\\]{0,32}") {
+            let _ = tokenize(&input);
+            let _ = crate::Context::new().render(&input);
+        }
+        #[test]
+        fn parsing_any_variable_is_total(input in "\\PC*") {
+            let _ = input.parse::<Variable>();
+        }
+        #[test]
+        fn text_without_blocks_is_a_single_token(input in "[^{]+") {
+            prop_assert_eq!(tokenize(&input), Ok(vec![Token::Str(input.as_str().into())]));
+        }
+        #[test]
+        fn text_tokens_reconstruct_input_around_variables(pre in "([^{]*[^{\\\\])?", post in "[^{]*") {
+            let input = format!("{pre}{{{{ x }}}}{post}");
+            let tokens = tokenize(&input).unwrap();
+            let text = tokens
+                .iter()
+                .filter_map(|t| match t {
+                    Token::Str(s) => Some(s.as_ref()),
+                    _ => None,
+                })
+                .collect::<String>();
+            prop_assert_eq!(text, format!("{pre}{post}"));
+        }
+    }
+
     #[test]
     fn location_adds_correctly() {
         assert_eq!(
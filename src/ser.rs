@@ -0,0 +1,643 @@
+//! Conversion of [`Serialize`] types into [`Value`]s, with the `serde` feature
+use std::fmt::Display;
+
+use serde::{
+    ser::{self, Impossible},
+    Serialize,
+};
+
+use crate::{Context, Object, Value};
+
+type Result<T, E = SerializeError> = std::result::Result<T, E>;
+
+/// Errors from converting a [`Serialize`] type into a [`Value`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SerializeError {
+    /// A map key serialized to something other than a string, number, bool or char
+    ///
+    /// Contains what the key was, e.g. `"list"`
+    UnsupportedKey(&'static str),
+    /// [`Context::from_serialize`] was given something other than a struct or map
+    ///
+    /// Contains what it was given, e.g. `"string"`
+    NotAnObject(&'static str),
+    /// Error reported by the [`Serialize`] implementation
+    Custom(String),
+}
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::UnsupportedKey(kind) => write!(
+                f,
+                "map keys must be strings, numbers, bools or chars, found {kind}"
+            ),
+            SerializeError::NotAnObject(kind) => {
+                write!(
+                    f,
+                    "expected a struct or map to define variables, found {kind}"
+                )
+            }
+            SerializeError::Custom(msg) => f.write_str(msg),
+        }
+    }
+}
+impl std::error::Error for SerializeError {}
+impl ser::Error for SerializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// What kind of value `value` is, for errors
+fn kind(value: &Value<'_>) -> &'static str {
+    match value {
+        Value::String(_) => "string",
+        Value::Object(_) => "map",
+        Value::List(_) => "list",
+    }
+}
+
+impl Value<'static> {
+    /// Convert any [`Serialize`] type into a value
+    ///
+    /// Structs and maps become [`Object`]s, sequences and tuples become lists and everything else
+    /// becomes a string. `None` and `()` are empty strings. Enum variants with data become an object
+    /// with the variant name as its only property, unit variants are the name of the variant.
+    ///
+    /// ```
+    /// # use handybars::{Object, Value};
+    /// #[derive(serde::Serialize)]
+    /// struct Server {
+    ///     host: &'static str,
+    ///     ports: Vec<u16>,
+    /// }
+    /// let value = Value::from_serialize(&Server { host: "web", ports: vec![80, 443] });
+    /// assert_eq!(
+    ///     value,
+    ///     Ok(Object::new()
+    ///         .with_property("host", "web")
+    ///         .with_property("ports", vec![80, 443])
+    ///         .into())
+    /// );
+    /// ```
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        value.serialize(ValueSerializer)
+    }
+}
+
+impl Context<'static> {
+    /// Create a context with the fields of a struct (or entries of a map) as its variables
+    ///
+    /// See [`Value::from_serialize`] for how values are converted.
+    ///
+    /// ```
+    /// # use handybars::Context;
+    /// #[derive(serde::Serialize)]
+    /// struct Release {
+    ///     name: String,
+    ///     replicas: u32,
+    /// }
+    /// let ctx = Context::from_serialize(&Release { name: "web".to_owned(), replicas: 3 }).unwrap();
+    /// assert_eq!(ctx.render("{{ name }}: {{ replicas }}"), Ok("web: 3".to_owned()));
+    /// ```
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        match Value::from_serialize(value)? {
+            Value::Object(o) => Ok(Self::from_object(o)),
+            v => Err(SerializeError::NotAnObject(kind(&v))),
+        }
+    }
+}
+
+/// Serializer producing a [`Value`]
+struct ValueSerializer;
+
+macro_rules! serialize_display {
+    ($($method:ident($typ:ty)),* $(,)?) => {
+        $(
+            fn $method(self, v: $typ) -> Result<Value<'static>> {
+                Ok(Value::from(v))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    serialize_display! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value<'static>> {
+        Ok(Value::from(v.to_owned()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value<'static>> {
+        Ok(v.iter().copied().collect())
+    }
+    fn serialize_none(self) -> Result<Value<'static>> {
+        self.serialize_unit()
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value<'static>> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Value<'static>> {
+        Ok(Value::String("".into()))
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Value<'static>> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Value<'static>> {
+        Ok(Value::from(variant))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Value<'static>> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value<'static>> {
+        Ok(Object::new()
+            .with_property(variant, value.serialize(self)?)
+            .into())
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<SeqSerializer>> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer {
+            object: Object::new(),
+            key: None,
+        })
+    }
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<MapSerializer> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<MapSerializer>> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+/// Serializer for sequences and tuples, producing a list
+struct SeqSerializer {
+    items: Vec<Value<'static>>,
+}
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+}
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value<'static>> {
+        Ok(Value::List(self.items))
+    }
+}
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value<'static>> {
+        ser::SerializeSeq::end(self)
+    }
+}
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value<'static>> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Serializer for maps and structs, producing an [`Object`]
+struct MapSerializer {
+    object: Object<'static>,
+    /// Key of the entry whose value is serialized next
+    key: Option<String>,
+}
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        self.object
+            .add_property(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Value<'static>> {
+        Ok(Value::Object(self.object))
+    }
+}
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.object
+            .add_property(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Value<'static>> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Serializer for enum variants with data, producing an [`Object`] with the variant as its only
+/// property
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &'static str, value: Value<'static>) -> Value<'static> {
+        Object::new().with_property(variant, value).into()
+    }
+}
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.inner.push(value)
+    }
+    fn end(self) -> Result<Value<'static>> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Value<'static>;
+    type Error = SerializeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+    fn end(self) -> Result<Value<'static>> {
+        let value = ser::SerializeMap::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+/// Serializer for map keys, which must be scalars
+struct KeySerializer;
+
+macro_rules! serialize_key {
+    ($($method:ident($typ:ty)),* $(,)?) => {
+        $(
+            fn $method(self, v: $typ) -> Result<String> {
+                Ok(v.to_string())
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerializeError;
+    type SerializeSeq = Impossible<String, SerializeError>;
+    type SerializeTuple = Impossible<String, SerializeError>;
+    type SerializeTupleStruct = Impossible<String, SerializeError>;
+    type SerializeTupleVariant = Impossible<String, SerializeError>;
+    type SerializeMap = Impossible<String, SerializeError>;
+    type SerializeStruct = Impossible<String, SerializeError>;
+    type SerializeStructVariant = Impossible<String, SerializeError>;
+
+    serialize_key! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<String> {
+        Err(SerializeError::UnsupportedKey("bytes"))
+    }
+    fn serialize_none(self) -> Result<String> {
+        Err(SerializeError::UnsupportedKey("none"))
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<String> {
+        Err(SerializeError::UnsupportedKey("unit"))
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<String> {
+        Ok(variant.to_owned())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<String> {
+        Err(SerializeError::UnsupportedKey("enum variant with data"))
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(SerializeError::UnsupportedKey("list"))
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> {
+        Err(SerializeError::UnsupportedKey("tuple"))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(SerializeError::UnsupportedKey("tuple"))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(SerializeError::UnsupportedKey("enum variant with data"))
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(SerializeError::UnsupportedKey("map"))
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        Err(SerializeError::UnsupportedKey("struct"))
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(SerializeError::UnsupportedKey("enum variant with data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::Serialize;
+
+    use super::*;
+    use crate::Variable;
+
+    #[derive(Serialize, PartialEq, Eq, Hash)]
+    enum Mode {
+        Fast,
+        Limit(u32),
+        Range(u8, u8),
+        Custom { name: &'static str },
+    }
+
+    #[test]
+    fn scalars_become_strings() {
+        assert_eq!(Value::from_serialize(&42u8), Ok("42".into()));
+        assert_eq!(Value::from_serialize(&-1.5f64), Ok("-1.5".into()));
+        assert_eq!(Value::from_serialize(&true), Ok("true".into()));
+        assert_eq!(Value::from_serialize(&'x'), Ok("x".into()));
+        assert_eq!(Value::from_serialize("text"), Ok("text".into()));
+        assert_eq!(Value::from_serialize(&None::<u8>), Ok("".into()));
+        assert_eq!(Value::from_serialize(&Some(1)), Ok("1".into()));
+        assert_eq!(Value::from_serialize(&()), Ok("".into()));
+    }
+
+    #[test]
+    fn sequences_become_lists() {
+        assert_eq!(
+            Value::from_serialize(&vec![vec![1], vec![]]),
+            Ok(Value::List(vec![
+                Value::List(vec!["1".into()]),
+                Value::List(vec![])
+            ]))
+        );
+        assert_eq!(
+            Value::from_serialize(&(1, "a")),
+            Ok(Value::List(vec!["1".into(), "a".into()]))
+        );
+    }
+
+    #[test]
+    fn maps_and_structs_become_objects() {
+        #[derive(Serialize)]
+        struct Inner(u8);
+        #[derive(Serialize)]
+        struct Outer {
+            inner: Inner,
+            map: BTreeMap<u8, bool>,
+        }
+        let value = Value::from_serialize(&Outer {
+            inner: Inner(1),
+            map: [(2, false)].into(),
+        });
+        assert_eq!(
+            value,
+            Ok(Object::new()
+                .with_property("inner", "1")
+                .with_property("map", Object::new().with_property("2", false))
+                .into())
+        );
+    }
+
+    #[test]
+    fn enums_are_externally_tagged() {
+        let modes = [
+            Mode::Fast,
+            Mode::Limit(3),
+            Mode::Range(1, 2),
+            Mode::Custom { name: "x" },
+        ];
+        assert_eq!(
+            Value::from_serialize(&modes),
+            Ok(Value::List(vec![
+                "Fast".into(),
+                Object::new().with_property("Limit", "3").into(),
+                Object::new().with_property("Range", vec![1, 2]).into(),
+                Object::new()
+                    .with_property("Custom", Object::new().with_property("name", "x"))
+                    .into(),
+            ]))
+        );
+    }
+
+    #[test]
+    fn map_keys_must_be_scalars() {
+        let map: HashMap<_, _> = [(Mode::Fast, 1)].into();
+        assert_eq!(
+            Value::from_serialize(&map),
+            Ok(Object::new().with_property("Fast", "1").into())
+        );
+        let map: HashMap<_, _> = [((1, 2), 1)].into();
+        assert_eq!(
+            Value::from_serialize(&map),
+            Err(SerializeError::UnsupportedKey("tuple"))
+        );
+        let map: HashMap<_, _> = [(Mode::Limit(1), 1)].into();
+        assert_eq!(
+            Value::from_serialize(&map),
+            Err(SerializeError::UnsupportedKey("enum variant with data"))
+        );
+    }
+
+    #[test]
+    fn context_is_built_from_the_top_level_fields() {
+        #[derive(Serialize)]
+        struct Deploy {
+            name: &'static str,
+            #[serde(rename = "app.kubernetes.io/part-of")]
+            part_of: &'static str,
+            ports: Vec<u16>,
+        }
+        let ctx = Context::from_serialize(&Deploy {
+            name: "web",
+            part_of: "shop",
+            ports: vec![80, 443],
+        })
+        .unwrap();
+        assert_eq!(
+            ctx.render(
+                "{{ name }} {{ [app.kubernetes.io/part-of] }} {{#each ports}}{{ this }};{{/each}}"
+            ),
+            Ok("web shop 80;443;".to_owned())
+        );
+        assert_eq!(
+            ctx.get_value(&Variable::single("name")),
+            Some(&Value::from("web"))
+        );
+        assert_eq!(
+            Context::from_serialize(&[1, 2]),
+            Err(SerializeError::NotAnObject("list"))
+        );
+    }
+
+    #[test]
+    fn custom_errors_are_forwarded() {
+        struct Failing;
+        impl Serialize for Failing {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(ser::Error::custom("no"))
+            }
+        }
+        assert_eq!(
+            Value::from_serialize(&[Failing]),
+            Err(SerializeError::Custom("no".to_owned()))
+        );
+        assert_eq!(SerializeError::Custom("no".to_owned()).to_string(), "no");
+    }
+}
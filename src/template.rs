@@ -0,0 +1,220 @@
+use std::{fmt, io, str::FromStr};
+
+use crate::{
+    context::Error,
+    parse::{self, Node, Tokenize},
+    Context, Variable,
+};
+
+/// Template which has already been parsed
+///
+/// Parsing a template is done once by [`compile`](Template::compile), after which it can be rendered
+/// against any number of [`Context`]s without tokenizing the input again.
+///
+/// ```
+/// # use handybars::{Context, Template};
+/// let template = Template::compile("hello {{ name }}").unwrap();
+/// let ctx = Context::new().with_define("name".parse().unwrap(), "world");
+/// assert_eq!(template.render(&ctx), Ok("hello world".to_owned()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    nodes: Vec<Node<'static>>,
+}
+
+impl Template {
+    /// Parse a template so that it can be rendered later
+    ///
+    /// ```
+    /// # use handybars::Template;
+    /// assert!(Template::compile("{{ a.b }}").is_ok());
+    /// assert!(Template::compile("{{ a. }}").is_err());
+    /// ```
+    pub fn compile(input: &str) -> Result<Self, parse::Error> {
+        Self::compile_with_options(input, &parse::Options::new())
+    }
+    /// Parse a template using non-default options
+    ///
+    /// ```
+    /// # use handybars::{Template, parse::Options};
+    /// assert!(Template::compile("{{ a").is_err());
+    /// assert!(Template::compile_with_options("{{ a", &Options::new().lenient(true)).is_ok());
+    /// ```
+    pub fn compile_with_options(
+        input: &str,
+        options: &parse::Options,
+    ) -> Result<Self, parse::Error> {
+        let (nodes, result) = parse::build_tree(Tokenize::with_options(input, options));
+        result?;
+        Ok(Self {
+            nodes: nodes.into_iter().map(Node::into_owned).collect(),
+        })
+    }
+    /// Render the template using the variables defined in `ctx`
+    pub fn render(&self, ctx: &Context<'_>) -> Result<String, Error> {
+        ctx.render_template(self)
+    }
+    /// Render the template into a [`fmt::Write`]
+    ///
+    /// See [`Context::render_to_fmt`]
+    pub fn render_to_fmt(
+        &self,
+        ctx: &Context<'_>,
+        output: &mut impl fmt::Write,
+    ) -> Result<(), Error> {
+        ctx.render_template_to_fmt(self, output)
+    }
+    /// Render the template into an [`io::Write`]
+    ///
+    /// See [`Context::render_to_io`]
+    pub fn render_to_io(
+        &self,
+        ctx: &Context<'_>,
+        output: &mut impl io::Write,
+    ) -> Result<(), Error> {
+        ctx.render_template_to_io(self, output)
+    }
+    /// Nodes this template was parsed into
+    pub fn nodes(&self) -> &[Node<'static>] {
+        &self.nodes
+    }
+    /// Every variable referenced by this template, in order of use
+    ///
+    /// See [`parse::variables`] for a version which works on uncompiled input and includes locations.
+    ///
+    /// ```
+    /// # use handybars::{Template, Variable};
+    /// let template = Template::compile("{{ a }} {{#if d}}{{ b.c }}{{/if}}").unwrap();
+    /// assert_eq!(
+    ///     template.variables().collect::<Vec<_>>(),
+    ///     vec![&Variable::single("a"), &Variable::single("d"), &Variable::from_parts(["b", "c"])]
+    /// );
+    /// ```
+    pub fn variables(&self) -> impl Iterator<Item = &Variable<'static>> {
+        let mut vars = Vec::new();
+        walk(&self.nodes, &mut |node| match node {
+            Node::Variable(var, ..) => vars.push(var),
+            Node::Block(block) => vars.push(block.variable()),
+            Node::Helper(call, ..) => vars.extend(call.variables()),
+            _ => {}
+        });
+        vars.into_iter()
+    }
+    /// Names of the partials this template includes, in order of use
+    ///
+    /// Partials included by those partials are not listed.
+    ///
+    /// ```
+    /// # use handybars::Template;
+    /// let template = Template::compile("{{> header}}{{#if a}}{{> body}}{{/if}}").unwrap();
+    /// assert_eq!(template.partials().collect::<Vec<_>>(), vec!["header", "body"]);
+    /// ```
+    pub fn partials(&self) -> impl Iterator<Item = &str> {
+        let mut names = Vec::new();
+        walk(&self.nodes, &mut |node| {
+            if let Node::Partial(name, _) = node {
+                names.push(name.as_ref());
+            }
+        });
+        names.into_iter()
+    }
+}
+
+/// Call `f` with every node in `nodes`, including those inside of blocks
+fn walk<'t>(nodes: &'t [Node<'static>], f: &mut impl FnMut(&'t Node<'static>)) {
+    for node in nodes {
+        f(node);
+        if let Node::Block(block) = node {
+            walk(block.body(), f);
+            walk(block.else_body(), f);
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = parse::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::compile(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_is_cacheable() {
+        fn assert_cacheable<T: Clone + Send + Sync + 'static>() {}
+        assert_cacheable::<Template>();
+    }
+
+    #[test]
+    fn compiled_template_outlives_its_input() {
+        let template = {
+            let input = String::from("a {{ b }} c");
+            Template::compile(&input).unwrap()
+        };
+        assert_eq!(
+            template.nodes(),
+            &[
+                Node::Str("a ".into()),
+                Node::Variable(
+                    Variable::single("b"),
+                    parse::Location::new(2, 0),
+                    "{{ b }}".into()
+                ),
+                Node::Str(" c".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn compiled_template_renders_the_same_as_context() {
+        let input = "{{ x.y }} and {{ z }}";
+        let ctx = Context::new()
+            .with_define("x.y".parse().unwrap(), "1")
+            .with_define("z".parse().unwrap(), "2");
+        let template: Template = input.parse().unwrap();
+        assert_eq!(template.render(&ctx), ctx.render(input));
+        assert_eq!(template.render(&ctx), Ok("1 and 2".to_owned()));
+    }
+
+    #[test]
+    fn compiled_template_streams_into_writers() {
+        let template = Template::compile("{{ a }} !").unwrap();
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        let mut bytes = Vec::new();
+        template.render_to_io(&ctx, &mut bytes).unwrap();
+        let mut string = String::new();
+        template.render_to_fmt(&ctx, &mut string).unwrap();
+        assert_eq!(bytes, string.as_bytes());
+        assert_eq!(string, "b !");
+    }
+
+    #[test]
+    fn compiled_template_renders_blocks() {
+        let template = Template::compile("{{#if a}}{{ a }}{{else}}none{{/if}}").unwrap();
+        assert_eq!(template.render(&Context::new()), Ok("none".to_owned()));
+        let ctx = Context::new().with_define(Variable::single("a"), "b");
+        assert_eq!(template.render(&ctx), Ok("b".to_owned()));
+    }
+
+    #[test]
+    fn template_variables_include_helper_arguments() {
+        let template = Template::compile(r#"{{ f a "b" k=c.d }}"#).unwrap();
+        assert_eq!(
+            template.variables().collect::<Vec<_>>(),
+            vec![&Variable::single("a"), &Variable::from_parts(["c", "d"])]
+        );
+    }
+
+    #[test]
+    fn rendering_template_reports_missing_variables() {
+        let template = Template::compile("{{ nope }}").unwrap();
+        assert_eq!(
+            template.render(&Context::new()),
+            Err(Error::MissingVariable(Variable::single("nope")))
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Reading values from TOML, with the `toml` feature
+//!
+//! Tables become [`Object`]s and arrays become lists. Everything else becomes a string, with
+//! dates and times kept as written in TOML.
+//!
+//! ```
+//! # use handybars::Context;
+//! let ctx = Context::from_toml("[db]\nhost = \"localhost\"\nport = 5432").unwrap();
+//! assert_eq!(ctx.render("{{ db.host }}:{{ db.port }}"), Ok("localhost:5432".to_owned()));
+//! ```
+use crate::{Context, Object, Value};
+
+/// Error reported when reading TOML
+pub use ::toml::de::Error;
+
+/// Read a value from a TOML document, which is always an [`Object`]
+pub fn from_str(input: &str) -> Result<Value<'static>, Error> {
+    Ok(table(input.parse()?).into())
+}
+
+impl Context<'static> {
+    /// Create a context with the keys of a TOML document as its variables
+    ///
+    /// See the [`toml`](crate::toml) module for how values are converted.
+    pub fn from_toml(input: &str) -> Result<Self, Error> {
+        Ok(Self::from_object(table(input.parse()?)))
+    }
+}
+
+fn table(table: ::toml::Table) -> Object<'static> {
+    let mut object = Object::new();
+    for (key, value) in table {
+        object.add_property(key, convert(value));
+    }
+    object
+}
+
+fn convert(value: ::toml::Value) -> Value<'static> {
+    match value {
+        ::toml::Value::String(s) => s.into(),
+        ::toml::Value::Integer(i) => i.into(),
+        // debug formatting keeps the `.0` of whole numbers
+        ::toml::Value::Float(f) => format!("{f:?}").into(),
+        ::toml::Value::Boolean(b) => b.into(),
+        ::toml::Value::Datetime(d) => d.to_string().into(),
+        ::toml::Value::Array(a) => a.into_iter().map(convert).collect(),
+        ::toml::Value::Table(t) => table(t).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_become_objects() {
+        let value = from_str(
+            r#"
+name = "web"
+ports = [80, 443]
+ratio = 1.0
+enabled = true
+released = 2024-01-02
+
+[db]
+host = "db.local"
+
+[[replicas]]
+zone = "a"
+"#,
+        );
+        assert_eq!(
+            value,
+            Ok(Object::new()
+                .with_property("name", "web")
+                .with_property("ports", vec![80, 443])
+                .with_property("ratio", "1.0")
+                .with_property("enabled", "true")
+                .with_property("released", "2024-01-02")
+                .with_property("db", Object::new().with_property("host", "db.local"))
+                .with_property("replicas", vec![Object::new().with_property("zone", "a")])
+                .into())
+        );
+    }
+
+    #[test]
+    fn errors_have_line_and_column() {
+        let err = Context::from_toml("a = 1\nb = ").unwrap_err();
+        assert!(err.to_string().contains("line 2, column 5"), "{err}");
+        assert_eq!(err.span(), Some(10..10));
+    }
+
+    #[test]
+    fn context_from_toml() {
+        let ctx = Context::from_toml("[app]\n\"kubernetes.io/name\" = \"web\"").unwrap();
+        assert_eq!(
+            ctx.render("{{ app.[kubernetes.io/name] }}"),
+            Ok("web".to_owned())
+        );
+    }
+}
@@ -13,26 +13,25 @@ impl<'a> Object<'a> {
     }
     /// Add a property to an object
     ///
-    /// Panics: if name contains a `.`
+    /// Names may contain any characters, in templates those which are not valid in variables
+    /// (such as `.`) are referred to with brackets.
     ///
-    /// ```should_panic
-    /// # use handybars::Object;
+    /// ```
+    /// # use handybars::{Context, Object};
     /// let mut obj = Object::new();
-    /// obj.add_property("a.b", "c"); // boom
+    /// obj.add_property("app.kubernetes.io/name", "web");
+    /// let ctx = Context::new().with_define("labels".parse().unwrap(), obj);
+    /// assert_eq!(ctx.render("{{ labels.[app.kubernetes.io/name] }}"), Ok("web".to_owned()));
     /// ```
     pub fn add_property(
         &mut self,
         name: impl Into<Cow<'a, str>>,
         value: impl Into<Value<'a>>,
     ) -> &mut Self {
-        let name = name.into();
-        assert!(!name.contains('.'), "property name may not contain dots");
-        self.values.insert(name, value.into());
+        self.values.insert(name.into(), value.into());
         self
     }
     /// Add a property with builder syntax
-    ///
-    /// Panics: if name contains a '.'
     pub fn with_property(
         mut self,
         name: impl Into<Cow<'a, str>>,
@@ -55,12 +54,36 @@ pub enum Value<'a> {
     String(Cow<'a, str>),
     /// Object with additional level of path
     Object(Object<'a>),
+    /// Sequence of values, see `{{#each}}`
+    List(Vec<Value<'a>>),
 }
 impl<'a> From<Object<'a>> for Value<'a> {
     fn from(value: Object<'a>) -> Self {
         Self::Object(value)
     }
 }
+impl<'a, T: Into<Value<'a>>> From<Vec<T>> for Value<'a> {
+    fn from(value: Vec<T>) -> Self {
+        Self::List(value.into_iter().map(Into::into).collect())
+    }
+}
+impl<'a, T: Into<Value<'a>> + Clone> From<&[T]> for Value<'a> {
+    fn from(value: &[T]) -> Self {
+        Self::List(value.iter().cloned().map(Into::into).collect())
+    }
+}
+/// Collect into a [`List`](Value::List)
+///
+/// ```
+/// # use handybars::Value;
+/// let ports: Value = (8080..8082).collect();
+/// assert_eq!(ports, Value::List(vec!["8080".into(), "8081".into()]));
+/// ```
+impl<'a, T: Into<Value<'a>>> FromIterator<T> for Value<'a> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::List(iter.into_iter().map(Into::into).collect())
+    }
+}
 impl<'a> From<Cow<'a, str>> for Value<'a> {
     fn from(value: Cow<'a, str>) -> Self {
         Self::String(value)
@@ -141,6 +164,16 @@ impl<'a> Value<'a> {
         }
     }
 
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn as_list(&self) -> Option<&[Value<'a>]> {
+        if let Self::List(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
     /// Returns `true` if the value is [`Object`].
     ///
     /// [`Object`]: Value::Object
@@ -156,6 +189,39 @@ impl<'a> Value<'a> {
     pub fn is_string(&self) -> bool {
         matches!(self, Self::String(..))
     }
+
+    /// Returns `true` if the value is [`List`].
+    ///
+    /// [`List`]: Value::List
+    #[must_use]
+    pub fn is_list(&self) -> bool {
+        matches!(self, Self::List(..))
+    }
+
+    /// Whether the value counts as true for blocks such as `{{#if value}}`
+    ///
+    /// Strings are truthy unless they are empty or `"false"`, so that values defined from a `bool`
+    /// behave as expected. Objects and lists are truthy if they are not empty. Variables which are
+    /// not defined at all are never truthy.
+    ///
+    /// ```
+    /// # use handybars::{Object, Value};
+    /// assert!(Value::from("yes").is_truthy());
+    /// assert!(Value::from("0").is_truthy());
+    /// assert!(!Value::from("").is_truthy());
+    /// assert!(!Value::from(false).is_truthy());
+    /// assert!(!Value::from(Object::new()).is_truthy());
+    /// assert!(Value::from(Object::new().with_property("a", "")).is_truthy());
+    /// assert!(!Value::from(Vec::<String>::new()).is_truthy());
+    /// ```
+    #[must_use]
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::String(s) => !s.is_empty() && s != "false",
+            Self::Object(o) => !o.values.is_empty(),
+            Self::List(l) => !l.is_empty(),
+        }
+    }
 }
 impl From<String> for Value<'static> {
     fn from(value: String) -> Self {
@@ -166,7 +232,7 @@ impl From<String> for Value<'static> {
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
-    use crate::Value;
+    use crate::{Object, Value};
 
     #[test]
     fn value_from_hex() {
@@ -217,6 +283,28 @@ mod tests {
         assert_eq!(Value::String(Cow::from("42.242")), From::from(42.242f64));
     }
 
+    #[test]
+    fn value_truthiness() {
+        assert!(Value::from(true).is_truthy());
+        assert!(Value::from(" ").is_truthy());
+        assert!(!Value::from("false").is_truthy());
+        assert!(!Value::from(Option::<&str>::None).is_truthy());
+        assert!(Value::from(Object::new().with_property("a", "b")).is_truthy());
+        assert!(!Value::from(Object::new()).is_truthy());
+    }
+
+    #[test]
+    fn value_from_sequences() {
+        let expected = Value::List(vec!["a".into(), "b".into()]);
+        assert_eq!(expected, Value::from(vec!["a", "b"]));
+        assert_eq!(expected, Value::from(&["a", "b"][..]));
+        assert_eq!(expected, ['a', 'b'].into_iter().collect());
+        assert_eq!(
+            Value::List(vec![Value::List(vec!["1".into()])]),
+            Value::from(vec![vec![1]])
+        );
+    }
+
     #[test]
     fn value_from_char() {
         assert_eq!(Value::String(Cow::from("*")), From::from('*'));
@@ -0,0 +1,202 @@
+//! Reading values from YAML, with the `yaml` feature
+//!
+//! Mappings become [`Object`]s and sequences become lists. Everything else becomes a string,
+//! with `null` as an empty string. Tags are ignored and `<<` merge keys are applied.
+//!
+//! ```
+//! # use handybars::Context;
+//! let ctx = Context::from_yaml("db:\n  host: localhost\n  port: 5432").unwrap();
+//! assert_eq!(ctx.render("{{ db.host }}:{{ db.port }}"), Ok("localhost:5432".to_owned()));
+//! ```
+use serde_norway::Value as Yaml;
+
+use crate::{Context, Object, Value};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error reported when reading YAML
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The document is not valid YAML
+    Parse(serde_norway::Error),
+    /// A mapping key which is not a string, number or bool
+    ///
+    /// Contains what the key was, e.g. `"sequence"`
+    UnsupportedKey(&'static str),
+    /// [`Context::from_yaml`] was given something other than a mapping
+    ///
+    /// Contains what it was given, e.g. `"sequence"`
+    NotAnObject(&'static str),
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(e) => e.fmt(f),
+            Error::UnsupportedKey(kind) => write!(
+                f,
+                "mapping keys must be strings, numbers or bools, found {kind}"
+            ),
+            Error::NotAnObject(kind) => {
+                write!(f, "expected a mapping to define variables, found {kind}")
+            }
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<serde_norway::Error> for Error {
+    fn from(e: serde_norway::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+
+/// Read a value from a YAML document
+pub fn from_str(input: &str) -> Result<Value<'static>> {
+    let mut yaml: Yaml = serde_norway::from_str(input)?;
+    yaml.apply_merge()?;
+    convert(yaml)
+}
+
+impl Context<'static> {
+    /// Create a context with the keys of a YAML mapping as its variables
+    ///
+    /// An empty document creates an empty context. See the [`yaml`](crate::yaml) module for how
+    /// values are converted.
+    pub fn from_yaml(input: &str) -> Result<Self> {
+        let mut yaml: Yaml = serde_norway::from_str(input)?;
+        yaml.apply_merge()?;
+        match untag(yaml) {
+            Yaml::Null => Ok(Self::new()),
+            Yaml::Mapping(m) => Ok(Self::from_object(mapping(m)?)),
+            other => Err(Error::NotAnObject(kind(&other))),
+        }
+    }
+}
+
+/// What kind of value `yaml` is, for errors
+fn kind(yaml: &Yaml) -> &'static str {
+    match yaml {
+        Yaml::Null => "null",
+        Yaml::Bool(_) => "bool",
+        Yaml::Number(_) => "number",
+        Yaml::String(_) => "string",
+        Yaml::Sequence(_) => "sequence",
+        Yaml::Mapping(_) => "mapping",
+        Yaml::Tagged(_) => "tagged value",
+    }
+}
+
+/// `yaml` without any tags
+fn untag(yaml: Yaml) -> Yaml {
+    match yaml {
+        Yaml::Tagged(t) => untag(t.value),
+        other => other,
+    }
+}
+
+fn mapping(mapping: serde_norway::Mapping) -> Result<Object<'static>> {
+    let mut object = Object::new();
+    for (key, value) in mapping {
+        let key = match untag(key) {
+            Yaml::String(s) => s,
+            Yaml::Bool(b) => b.to_string(),
+            Yaml::Number(n) => n.to_string(),
+            other => return Err(Error::UnsupportedKey(kind(&other))),
+        };
+        object.add_property(key, convert(value)?);
+    }
+    Ok(object)
+}
+
+fn convert(yaml: Yaml) -> Result<Value<'static>> {
+    Ok(match yaml {
+        Yaml::Null => "".into(),
+        Yaml::Bool(b) => b.into(),
+        Yaml::Number(n) => n.to_string().into(),
+        Yaml::String(s) => s.into(),
+        Yaml::Sequence(s) => Value::List(s.into_iter().map(convert).collect::<Result<_>>()?),
+        Yaml::Mapping(m) => mapping(m)?.into(),
+        Yaml::Tagged(t) => convert(t.value)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mappings_become_objects() {
+        let value = from_str(
+            r#"
+name: web
+ports: [80, 443]
+enabled: true
+missing: ~
+ratio: !custom 1.5
+1: one
+db:
+  host: db.local
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            Object::new()
+                .with_property("name", "web")
+                .with_property("ports", vec![80, 443])
+                .with_property("enabled", "true")
+                .with_property("missing", "")
+                .with_property("ratio", "1.5")
+                .with_property("1", "one")
+                .with_property("db", Object::new().with_property("host", "db.local"))
+                .into()
+        );
+    }
+
+    #[test]
+    fn merge_keys_are_applied() {
+        let ctx = Context::from_yaml(
+            r#"
+base: &base
+  image: nginx
+  tag: "1.0"
+web:
+  <<: *base
+  tag: "2.0"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.render("{{ web.image }}:{{ web.tag }}"),
+            Ok("nginx:2.0".to_owned())
+        );
+    }
+
+    #[test]
+    fn unsupported_documents_are_errors() {
+        assert!(matches!(
+            from_str("[a]: b"),
+            Err(Error::UnsupportedKey("sequence"))
+        ));
+        assert!(matches!(
+            Context::from_yaml("- a"),
+            Err(Error::NotAnObject("sequence"))
+        ));
+        let err = Context::from_yaml("a: b\nc: [d").unwrap_err();
+        assert!(matches!(err, Error::Parse(_)));
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn empty_document_is_an_empty_context() {
+        let ctx = Context::from_yaml("# nothing here\n").unwrap();
+        assert_eq!(ctx.render("text"), Ok("text".to_owned()));
+    }
+}